@@ -0,0 +1,395 @@
+use std::path::PathBuf;
+
+use clap::{parser::ValueSource, ArgMatches, Args, Parser, Subcommand, ValueEnum};
+
+use crate::config::Seconds;
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// Parsed once at startup
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the adapter, which is the default without a subcommand
+    Run(RunArguments),
+    /// Query a probe of a running adapter and exit with 0 if it passes or 1 if it does not
+    Probe(ProbeArguments),
+    /// Send a notification to the notify socket, like systemd-notify
+    Notify(NotifyArguments),
+    /// Check or print the resolved configuration without running the adapter
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Report problems with the configuration and exit with 1 if any is an error
+    Check(SourceArguments),
+    /// Print the resolved configuration
+    Dump(DumpArguments),
+}
+
+#[derive(clap::Args)]
+pub struct RunArguments {
+    #[command(flatten)]
+    pub sources: SourceArguments,
+    /// A service to start and supervise, like `systemd` does, instead of waiting for one
+    #[arg(last = true, value_name = "COMMAND")]
+    pub command: Vec<String>,
+}
+
+/// Where the configuration is loaded from, besides the environment
+#[derive(clap::Args)]
+pub struct SourceArguments {
+    /// The path of a TOML configuration file
+    #[arg(long, env = "ADAPTER_CONFIG", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// The path of a systemd service unit file to import settings from
+    #[arg(long, env = "ADAPTER_UNIT_FILE", value_name = "PATH")]
+    pub unit_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub options: ConfigurationOptions,
+}
+
+/// Options for every configuration key, which take precedence over the environment
+#[derive(clap::Args)]
+#[command(next_help_heading = "Configuration")]
+pub struct ConfigurationOptions {
+    /// The path of the socket to create
+    #[arg(long, env = "NOTIFY_SOCKET", value_name = "PATH")]
+    notify_socket: Option<String>,
+    /// The port for the HTTP server to listen to
+    #[arg(long, env = "ADAPTER_PORT", value_name = "PORT")]
+    port: Option<String>,
+    /// Whether to reserialize all messages to standard output
+    #[arg(long, env = "ADAPTER_ECHO", value_name = "BOOL")]
+    echo: Option<String>,
+    /// Whether to log JSONL records to standard error
+    #[arg(long, env = "ADAPTER_LOG", value_name = "BOOL")]
+    log: Option<String>,
+    /// A file to log to instead of standard error, which the `reopen` signal action reopens
+    #[arg(long, env = "ADAPTER_LOG_FILE", value_name = "PATH")]
+    log_file: Option<String>,
+    /// The channel size to use for internal message-passing
+    #[arg(long, env = "ADAPTER_CHANNEL_SIZE", value_name = "SIZE")]
+    channel_size: Option<String>,
+    /// Whether /livez passes until a status change occurs
+    #[arg(long, env = "ADAPTER_INITIAL_LIVEZ", value_name = "BOOL")]
+    initial_livez: Option<String>,
+    /// Whether /readyz passes until a status change occurs
+    #[arg(long, env = "ADAPTER_INITIAL_READYZ", value_name = "BOOL")]
+    initial_readyz: Option<String>,
+    /// Whether to process `WATCHDOG_USEC` messages
+    #[arg(long, env = "ADAPTER_ALLOW_MESSAGE_WATCHDOG_USEC", value_name = "BOOL")]
+    allow_message_watchdog_usec: Option<String>,
+    /// Whether to process `EXTEND_TIMEOUT_USEC` messages
+    #[arg(
+        long,
+        env = "ADAPTER_ALLOW_MESSAGE_EXTEND_TIMEOUT_USEC",
+        value_name = "BOOL"
+    )]
+    allow_message_extend_timeout_usec: Option<String>,
+    /// The address for the admin server to listen to, `unix:` and a path, or empty to disable it
+    #[arg(long, env = "ADAPTER_ADMIN_ADDRESS", value_name = "ADDRESS")]
+    admin_address: Option<String>,
+    /// The bearer token required by the admin server
+    #[arg(
+        long,
+        env = "ADAPTER_ADMIN_TOKEN",
+        value_name = "TOKEN",
+        hide_env_values = true
+    )]
+    admin_token: Option<String>,
+    /// Whether the admin server accepts messages on POST /notify
+    #[arg(long, env = "ADAPTER_ADMIN_NOTIFY", value_name = "BOOL")]
+    admin_notify: Option<String>,
+    /// The port for the gRPC health checking server to listen to
+    #[arg(long, env = "ADAPTER_GRPC_PORT", value_name = "PORT")]
+    grpc_port: Option<String>,
+    /// Status codes of /healthz, e.g. `pass=200,fail=503`
+    #[arg(long, env = "ADAPTER_HTTP_HEALTHZ_CODES", value_name = "CODES")]
+    http_healthz_codes: Option<String>,
+    /// Body format of /healthz: `json`, `text` or `empty`
+    #[arg(long, env = "ADAPTER_HTTP_HEALTHZ_FORMAT", value_name = "FORMAT")]
+    http_healthz_format: Option<String>,
+    /// Status codes of /livez, e.g. `pass=200,fail=503`
+    #[arg(long, env = "ADAPTER_HTTP_LIVEZ_CODES", value_name = "CODES")]
+    http_livez_codes: Option<String>,
+    /// Body format of /livez: `json`, `text` or `empty`
+    #[arg(long, env = "ADAPTER_HTTP_LIVEZ_FORMAT", value_name = "FORMAT")]
+    http_livez_format: Option<String>,
+    /// Status codes of /readyz, e.g. `pass=200,fail=503`
+    #[arg(long, env = "ADAPTER_HTTP_READYZ_CODES", value_name = "CODES")]
+    http_readyz_codes: Option<String>,
+    /// Body format of /readyz: `json`, `text` or `empty`
+    #[arg(long, env = "ADAPTER_HTTP_READYZ_FORMAT", value_name = "FORMAT")]
+    http_readyz_format: Option<String>,
+    /// Events changing /livez to true
+    #[arg(long, env = "ADAPTER_STATUS_LIVEZ_TRUE", value_name = "EVENTS")]
+    status_livez_true: Option<String>,
+    /// Events changing /livez to false
+    #[arg(long, env = "ADAPTER_STATUS_LIVEZ_FALSE", value_name = "EVENTS")]
+    status_livez_false: Option<String>,
+    /// Events changing /readyz to true
+    #[arg(long, env = "ADAPTER_STATUS_READYZ_TRUE", value_name = "EVENTS")]
+    status_readyz_true: Option<String>,
+    /// Events changing /readyz to false
+    #[arg(long, env = "ADAPTER_STATUS_READYZ_FALSE", value_name = "EVENTS")]
+    status_readyz_false: Option<String>,
+    /// Events shutting down the adapter
+    #[arg(long, env = "ADAPTER_STATUS_SHUTDOWN", value_name = "EVENTS")]
+    status_shutdown: Option<String>,
+    /// The exit code after each event that shuts down the adapter, e.g. `watchdog_timeout=10`
+    #[arg(long, env = "ADAPTER_STATUS_SHUTDOWN_CODES", value_name = "CODES")]
+    status_shutdown_codes: Option<String>,
+    /// How long to fail /readyz and keep serving after SIGTERM, before shutting down
+    #[arg(long, env = "ADAPTER_SHUTDOWN_DRAIN_SEC", value_name = "SPAN")]
+    shutdown_drain_sec: Option<String>,
+    /// What to wait for after draining: `none`, `stopping` for `STOPPING=1`, or `exit`
+    #[arg(long, env = "ADAPTER_SHUTDOWN_WAIT", value_name = "WAIT")]
+    shutdown_wait: Option<String>,
+    /// What to do on each signal, e.g. `SIGUSR2=reopen,SIGALRM=shutdown`
+    #[arg(long, env = "ADAPTER_SIGNAL_ACTIONS", value_name = "ACTIONS")]
+    signal_actions: Option<String>,
+    /// Whether to shut down once the service has run to completion, like a Kubernetes `Job`
+    #[arg(long, env = "ADAPTER_JOB", value_name = "BOOL")]
+    job: Option<String>,
+    /// How long the service may not send any message in job mode before it counts as finished
+    #[arg(long, env = "ADAPTER_JOB_SILENCE_SEC", value_name = "SPAN")]
+    job_silence_sec: Option<String>,
+    /// The URL of the Kubernetes API, e.g. of a mock server
+    #[arg(long, env = "ADAPTER_KUBERNETES_API_URL", value_name = "URL")]
+    kubernetes_api_url: Option<String>,
+    /// The service account token to authenticate to the Kubernetes API with
+    #[arg(long, env = "ADAPTER_KUBERNETES_TOKEN_FILE", value_name = "PATH")]
+    kubernetes_token_file: Option<String>,
+    /// The CA certificates to verify the Kubernetes API with
+    #[arg(long, env = "ADAPTER_KUBERNETES_CA_FILE", value_name = "PATH")]
+    kubernetes_ca_file: Option<String>,
+    /// The name of the `Pod` of the adapter, from the downward API
+    #[arg(long, env = "ADAPTER_KUBERNETES_POD_NAME", value_name = "NAME")]
+    kubernetes_pod_name: Option<String>,
+    /// The namespace of the `Pod` of the adapter, from the downward API
+    #[arg(
+        long,
+        env = "ADAPTER_KUBERNETES_POD_NAMESPACE",
+        value_name = "NAMESPACE"
+    )]
+    kubernetes_pod_namespace: Option<String>,
+    /// The UID of the `Pod` of the adapter, from the downward API
+    #[arg(long, env = "ADAPTER_KUBERNETES_POD_UID", value_name = "UID")]
+    kubernetes_pod_uid: Option<String>,
+    /// The type of a `Pod` condition to keep in line with /readyz, e.g. `example.com/Ready`
+    #[arg(long, env = "ADAPTER_KUBERNETES_CONDITION", value_name = "TYPE")]
+    kubernetes_condition: Option<String>,
+    /// How long to collect changes before sending them to the Kubernetes API
+    #[arg(long, env = "ADAPTER_KUBERNETES_BATCH_SEC", value_name = "SPAN")]
+    kubernetes_batch_sec: Option<String>,
+    /// Events to record as Kubernetes `Event`s of the `Pod`, e.g. `errno,watchdog_timeout`
+    #[arg(long, env = "ADAPTER_KUBERNETES_EVENTS", value_name = "EVENTS")]
+    kubernetes_events: Option<String>,
+    /// How often each kind of Kubernetes `Event` may be sent at most
+    #[arg(
+        long,
+        env = "ADAPTER_KUBERNETES_EVENT_INTERVAL_SEC",
+        value_name = "SPAN"
+    )]
+    kubernetes_event_interval_sec: Option<String>,
+    /// How long to wait for `READY=1`, like `TimeoutStartSec=`, e.g. `90`, `1min 30s` or `infinity`
+    #[arg(long, env = "ADAPTER_UNIT_TIMEOUT_START_SEC", value_name = "SPAN")]
+    unit_timeout_start_sec: Option<String>,
+    /// How long to wait for `WATCHDOG=1`, like `WatchdogSec=`, e.g. `30s`, or `0` to disable it
+    #[arg(long, env = "ADAPTER_UNIT_WATCHDOG_SEC", value_name = "SPAN")]
+    unit_watchdog_sec: Option<String>,
+    /// How long to wait for the adapter to shut down, like `TimeoutStopSec=`
+    #[arg(long, env = "ADAPTER_UNIT_TIMEOUT_STOP_SEC", value_name = "SPAN")]
+    unit_timeout_stop_sec: Option<String>,
+    /// The signal to send to the main process when the watchdog fires, like `WatchdogSignal=`
+    #[arg(long, env = "ADAPTER_UNIT_WATCHDOG_SIGNAL", value_name = "SIGNAL")]
+    unit_watchdog_signal: Option<String>,
+    /// How long to wait for the main process to exit after the watchdog signal before killing
+    /// it, like `TimeoutAbortSec=`
+    #[arg(long, env = "ADAPTER_UNIT_TIMEOUT_ABORT_SEC", value_name = "SPAN")]
+    unit_timeout_abort_sec: Option<String>,
+    /// How long the adapter runs before it shuts down, like `RuntimeMaxSec=`
+    #[arg(long, env = "ADAPTER_UNIT_RUNTIME_MAX_SEC", value_name = "SPAN")]
+    unit_runtime_max_sec: Option<String>,
+    /// Whether to process notifications, like `NotifyAccess=`: `none`, `main`, `exec` or `all`
+    #[arg(long, env = "ADAPTER_UNIT_NOTIFY_ACCESS", value_name = "ACCESS")]
+    unit_notify_access: Option<String>,
+    /// When to restart a supervised service, like `Restart=`: `no`, `on-failure`, `on-watchdog` or
+    /// `always`
+    #[arg(long, env = "ADAPTER_UNIT_RESTART", value_name = "POLICY")]
+    unit_restart: Option<String>,
+    /// How long to wait before restarting a supervised service, like `RestartSec=`
+    #[arg(long, env = "ADAPTER_UNIT_RESTART_SEC", value_name = "SPAN")]
+    unit_restart_sec: Option<String>,
+    /// How many starts of a supervised service are allowed within the interval, like
+    /// `StartLimitBurst=`
+    #[arg(long, env = "ADAPTER_UNIT_START_LIMIT_BURST", value_name = "COUNT")]
+    unit_start_limit_burst: Option<String>,
+    /// The interval of the start limit, like `StartLimitIntervalSec=`, or `0` to disable it
+    #[arg(
+        long,
+        env = "ADAPTER_UNIT_START_LIMIT_INTERVAL_SEC",
+        value_name = "SPAN"
+    )]
+    unit_start_limit_interval_sec: Option<String>,
+}
+
+/// The configuration options given on the command line by configuration key, which are the
+/// names of the options
+pub fn options(matches: &ArgMatches) -> Vec<(String, String)> {
+    let mut matches = matches;
+    while let Some((_, subcommand)) = matches.subcommand() {
+        matches = subcommand;
+    }
+    let keys = ConfigurationOptions::augment_args(clap::Command::new("options"));
+    matches
+        .ids()
+        .filter(|id| {
+            keys.get_arguments()
+                .any(|argument| argument.get_id() == *id)
+        })
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .filter_map(|id| {
+            let value = matches.try_get_one::<String>(id.as_str()).ok()??;
+            Some((id.to_string(), value.clone()))
+        })
+        .collect()
+}
+
+#[derive(clap::Args)]
+pub struct DumpArguments {
+    /// The format to print the configuration in
+    #[arg(long, value_enum, default_value = "env")]
+    pub format: DumpFormat,
+    #[command(flatten)]
+    pub sources: SourceArguments,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DumpFormat {
+    /// Environment variable assignments
+    Env,
+    /// A configuration file
+    Toml,
+    /// A JSON object, where unset keys are null
+    Json,
+}
+
+#[derive(clap::Args)]
+pub struct ProbeArguments {
+    /// The probe to query
+    pub probe: ProbeName,
+    /// The base URL of the adapter's HTTP server
+    #[arg(
+        long,
+        default_value = "http://127.0.0.1:8089",
+        conflicts_with = "socket"
+    )]
+    pub url: String,
+    /// The path of a Unix domain socket the admin server listens to, instead of a URL
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+    /// The bearer token of the admin server
+    #[arg(long, env = "ADAPTER_ADMIN_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+    /// How long to wait for a response, e.g. `5` or `500ms`
+    #[arg(long, default_value = "5")]
+    pub timeout: Seconds,
+    /// Print the JSON response to standard output
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProbeName {
+    Healthz,
+    Livez,
+    Readyz,
+}
+
+impl ProbeName {
+    pub fn path(self) -> &'static str {
+        match self {
+            ProbeName::Healthz => "/healthz",
+            ProbeName::Livez => "/livez",
+            ProbeName::Readyz => "/readyz",
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(clap::Args)]
+pub struct NotifyArguments {
+    /// Assignments to send, e.g. `WATCHDOG=1`
+    #[arg(value_name = "KEY=VALUE")]
+    pub assignments: Vec<String>,
+    /// Send `READY=1`
+    #[arg(long)]
+    pub ready: bool,
+    /// Send `RELOADING=1`
+    #[arg(long)]
+    pub reloading: bool,
+    /// Send `STOPPING=1`
+    #[arg(long)]
+    pub stopping: bool,
+    /// Send `STATUS=` with the given text
+    #[arg(long)]
+    pub status: Option<String>,
+    /// Send `MAINPID=` with the given PID, or the PID of the calling process
+    #[arg(long, value_name = "PID", num_args = 0..=1, require_equals = true)]
+    #[allow(clippy::option_option)]
+    pub pid: Option<Option<i32>>,
+    /// Exit with 0 if the notify socket exists, and with 1 otherwise, without sending anything
+    #[arg(long)]
+    pub booted: bool,
+    /// Wait until the adapter has processed the notification
+    #[arg(long)]
+    pub barrier: bool,
+    /// How long to wait for the barrier, e.g. `5`, `500ms` or `infinity`
+    #[arg(long, default_value = "5")]
+    pub timeout: Seconds,
+    /// The path of the notify socket, or its name in the abstract namespace prefixed with `@`
+    #[arg(
+        long,
+        env = "NOTIFY_SOCKET",
+        default_value = "/var/run/adapter/adapter.sock"
+    )]
+    pub socket: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{self, Configuration, STARTUP_KEYS};
+
+    #[test]
+    fn options_cover_every_configuration_key() {
+        let command = ConfigurationOptions::augment_args(clap::Command::new("options"));
+        let options: Vec<_> = command
+            .get_arguments()
+            .map(|argument| {
+                let env = argument.get_env().and_then(|env| env.to_str());
+                (argument.get_id().to_string(), env.map(String::from))
+            })
+            .collect();
+        let keys = Configuration::keys().expect("keys");
+        let expected: Vec<_> = keys
+            .iter()
+            .map(|key| (key.clone(), Some(config::variable(key))))
+            .collect();
+        assert_eq!(options, expected);
+        for key in STARTUP_KEYS {
+            assert!(keys.iter().any(|listed| listed == key), "{key}");
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use crate::validation::{Problem, Severity};
+
+/// Prints the problems of the configuration and returns whether it is free of errors
+pub fn check(problems: &[Problem]) -> bool {
+    for problem in problems {
+        println!("{problem}");
+    }
+    if problems.is_empty() {
+        println!("The configuration is valid");
+    }
+    !problems
+        .iter()
+        .any(|problem| problem.severity == Severity::Error)
+}
@@ -0,0 +1,166 @@
+use std::fmt::Write;
+
+use toml::{Table, Value as TomlValue};
+use valuable::{NamedValues, Valuable, Value, Visit};
+
+use crate::{
+    cli::DumpFormat,
+    config::{self, Configuration},
+    error::Error,
+};
+
+/// Prints the resolved configuration, leaving out keys that are unset
+pub fn dump(config: &Configuration, format: DumpFormat) -> Result<bool, Error> {
+    print!("{}", render(config, format)?);
+    Ok(true)
+}
+
+/// Writes the configuration in a format, as printed by `config dump` and served by `GET /config`
+///
+/// Secrets that are set are written as comments in `env` and `toml`, which can be read back in as
+/// a configuration, so that their redacted value cannot be mistaken for the secret itself.
+pub fn render(config: &Configuration, format: DumpFormat) -> Result<String, Error> {
+    let secrets: Vec<_> = config
+        .secrets()
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|_| key))
+        .collect();
+    let mut settings = settings(config);
+    if !matches!(format, DumpFormat::Json) {
+        settings.retain(|(key, _)| !secrets.contains(&key.as_str()));
+    }
+    let mut dump = String::new();
+    match format {
+        DumpFormat::Env => {
+            for key in secrets {
+                let _ = writeln!(dump, "# {} is set, but not shown", config::variable(key));
+            }
+            for (key, value) in settings {
+                if let Some(value) = value {
+                    let _ = writeln!(dump, "{}={}", config::variable(&key), env_value(&value));
+                }
+            }
+            Ok(dump)
+        }
+        DumpFormat::Toml => {
+            for key in secrets {
+                let _ = writeln!(dump, "# {key} is set, but not shown");
+            }
+            let table: Table = settings
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, value?)))
+                .collect();
+            dump.push_str(&toml::to_string(&table).map_err(Error::ConfigDumpToml)?);
+            Ok(dump)
+        }
+        DumpFormat::Json => {
+            let object: serde_json::Map<_, _> = settings
+                .into_iter()
+                .map(|(key, value)| (key, value.map_or(serde_json::Value::Null, json_value)))
+                .collect();
+            serde_json::to_string_pretty(&object)
+                .map(|json| json + "\n")
+                .map_err(Error::ConfigDumpJson)
+        }
+    }
+}
+
+/// The values of the configuration by key, where `None` is unset
+fn settings(config: &Configuration) -> Vec<(String, Option<TomlValue>)> {
+    struct Settings(Vec<(String, Option<TomlValue>)>);
+    impl Visit for Settings {
+        fn visit_value(&mut self, _: Value<'_>) {}
+
+        fn visit_named_fields(&mut self, named_values: &NamedValues<'_>) {
+            for (field, value) in named_values {
+                self.0.push((field.name().into(), setting(*value)));
+            }
+        }
+    }
+
+    let mut settings = Settings(Vec::new());
+    config.visit(&mut settings);
+    settings.0
+}
+
+/// Converts a value, unwrapping single-field structs like `Seconds` and `EventList`
+fn setting(value: Value<'_>) -> Option<TomlValue> {
+    struct Values(Vec<Option<TomlValue>>);
+    impl Visit for Values {
+        fn visit_value(&mut self, value: Value<'_>) {
+            self.0.push(setting(value));
+        }
+
+        fn visit_unnamed_fields(&mut self, values: &[Value<'_>]) {
+            self.0.extend(values.iter().map(|value| setting(*value)));
+        }
+    }
+
+    match value {
+        Value::Bool(value) => Some(TomlValue::Boolean(value)),
+        Value::F32(value) => Some(TomlValue::Float(value.into())),
+        // Like the environment variables of time spans
+        Value::F64(value) if value.is_infinite() => Some(TomlValue::String("infinity".into())),
+        Value::F64(value) => Some(TomlValue::Float(value)),
+        Value::String(value) => Some(TomlValue::String(value.into())),
+        Value::Listable(listable) => {
+            let mut values = Values(Vec::new());
+            listable.visit(&mut values);
+            Some(TomlValue::Array(values.0.into_iter().flatten().collect()))
+        }
+        Value::Structable(structable) => {
+            let mut values = Values(Vec::new());
+            structable.visit(&mut values);
+            match <[_; 1]>::try_from(values.0) {
+                Ok([value]) => value,
+                Err(_) => None,
+            }
+        }
+        value => value.as_i64().map(TomlValue::Integer),
+    }
+}
+
+/// Writes a value like its environment variable, where lists are comma-separated
+fn env_value(value: &TomlValue) -> String {
+    match value {
+        TomlValue::String(value) => value.clone(),
+        TomlValue::Array(values) => values.iter().map(env_value).collect::<Vec<_>>().join(","),
+        value => value.to_string(),
+    }
+}
+
+/// Converts a setting to JSON
+fn json_value(value: TomlValue) -> serde_json::Value {
+    match value {
+        TomlValue::Boolean(value) => value.into(),
+        TomlValue::Integer(value) => value.into(),
+        TomlValue::Float(value) => value.into(),
+        TomlValue::Array(values) => values.into_iter().map(json_value).collect(),
+        value => env_value(&value).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use envconfig::Envconfig;
+
+    use super::*;
+
+    #[test]
+    fn secrets_are_not_written_as_values() {
+        let variables = HashMap::from([("ADAPTER_ADMIN_TOKEN".into(), "hunter2".into())]);
+        let config = Configuration::init_from_hashmap(&variables).expect("valid configuration");
+
+        let env = render(&config, DumpFormat::Env).expect("env dump");
+        assert!(env.contains("# ADAPTER_ADMIN_TOKEN is set"));
+        assert!(!env.contains("ADAPTER_ADMIN_TOKEN="));
+        let toml = render(&config, DumpFormat::Toml).expect("toml dump");
+        assert!(toml.contains("# admin_token is set"));
+        assert!(!toml.contains("admin_token ="));
+        assert!(!render(&config, DumpFormat::Json)
+            .expect("json dump")
+            .contains("hunter2"));
+    }
+}
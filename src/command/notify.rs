@@ -0,0 +1,310 @@
+use std::{
+    io::IoSlice,
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd},
+        unix::{ffi::OsStrExt, process::parent_id},
+    },
+    path::Path,
+    time::Duration,
+};
+
+use nix::{
+    poll::{self, PollFd, PollFlags, PollTimeout},
+    sys::socket::{self, AddressFamily, ControlMessage, MsgFlags, SockFlag, SockType, UnixAddr},
+    unistd,
+};
+
+use crate::{cli::NotifyArguments, error::Error, message::Message};
+
+/// Sends the messages of the arguments to the notify socket
+pub fn notify(arguments: &NotifyArguments) -> Result<bool, Error> {
+    if arguments.booted {
+        return Ok(Path::new(&arguments.socket).exists());
+    }
+
+    let mut messages = Vec::new();
+    if arguments.ready {
+        messages.push(Message::Ready);
+    }
+    if arguments.reloading {
+        messages.push(Message::Reloading);
+    }
+    if arguments.stopping {
+        messages.push(Message::Stopping);
+    }
+    if let Some(status) = &arguments.status {
+        messages.push(Message::Status(status.clone()));
+    }
+    if let Some(pid) = &arguments.pid {
+        let pid = match pid {
+            Some(pid) => *pid,
+            // Like systemd-notify, the calling shell is the main process by default
+            None => i32::try_from(parent_id()).map_err(|_| Error::NotifyPid)?,
+        };
+        messages.push(Message::MainPID(pid));
+    }
+    for assignment in &arguments.assignments {
+        messages.push(assignment.parse()?);
+    }
+
+    if messages.is_empty() && !arguments.barrier {
+        return Err(Error::NotifyEmpty);
+    }
+
+    let address = if let Some(name) = arguments.socket.strip_prefix('@') {
+        UnixAddr::new_abstract(name.as_bytes())
+    } else {
+        UnixAddr::new(Path::new(&arguments.socket).as_os_str().as_bytes())
+    }
+    .map_err(Error::NotifySend)?;
+    let socket = socket::socket(
+        AddressFamily::Unix,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(Error::NotifySend)?;
+
+    if !messages.is_empty() {
+        let datagram = messages
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>()
+            .join("\n");
+        socket::sendmsg(
+            socket.as_raw_fd(),
+            &[IoSlice::new(datagram.as_bytes())],
+            &[],
+            MsgFlags::empty(),
+            Some(&address),
+        )
+        .map_err(Error::NotifySend)?;
+    }
+
+    if arguments.barrier {
+        barrier(&socket, &address, arguments.timeout.into())?;
+    }
+
+    Ok(true)
+}
+
+/// Waits until the receiver has processed all previous messages, like `sd_notify_barrier`
+fn barrier(socket: &OwnedFd, address: &UnixAddr, timeout: Duration) -> Result<(), Error> {
+    let (read, write) = unistd::pipe().map_err(Error::NotifySend)?;
+
+    let barrier = String::from(Message::Barrier);
+    socket::sendmsg(
+        socket.as_raw_fd(),
+        &[IoSlice::new(barrier.as_bytes())],
+        &[ControlMessage::ScmRights(&[write.as_raw_fd()])],
+        MsgFlags::empty(),
+        Some(address),
+    )
+    .map_err(Error::NotifySend)?;
+    // The receiver now holds the only copy of the write end and closes it once processed
+    drop(write);
+
+    let timeout = if timeout == Duration::MAX {
+        PollTimeout::NONE
+    } else {
+        PollTimeout::try_from(timeout).map_err(|_| Error::NotifyBarrierTimeout)?
+    };
+    let mut descriptors = [PollFd::new(read.as_fd(), PollFlags::POLLIN)];
+    match poll::poll(&mut descriptors, timeout).map_err(Error::NotifySend)? {
+        0 => Err(Error::NotifyBarrierTimeout),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::IoSliceMut,
+        os::{
+            fd::{FromRawFd, RawFd},
+            linux::net::SocketAddrExt,
+            unix::net::{self, UnixDatagram},
+        },
+        process,
+        sync::Arc,
+        thread,
+    };
+
+    use nix::{cmsg_space, sys::socket::ControlMessageOwned};
+    use tokio::{
+        sync::{broadcast, mpsc, RwLock},
+        task,
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::{
+        config::{ConfigString, Configuration},
+        event::Event,
+        server::uds,
+        service::SharedService,
+        testing::TempDir,
+    };
+
+    /// Binds a receiver in the abstract namespace, and returns it with the socket to notify
+    fn bind(name: &str) -> (UnixDatagram, String) {
+        let name = format!("notify-test-{}-{name}", process::id());
+        let address = net::SocketAddr::from_abstract_name(&name).expect("address");
+        let receiver = UnixDatagram::bind_addr(&address).expect("receiver");
+        (receiver, format!("@{name}"))
+    }
+
+    /// Receives a datagram along with the descriptors sent with it
+    fn receive(receiver: &UnixDatagram) -> (String, Vec<OwnedFd>) {
+        let mut buffer = [0u8; 1024];
+        let mut iov = [IoSliceMut::new(&mut buffer)];
+        let mut control = cmsg_space!([RawFd; 1]);
+        let message = socket::recvmsg::<()>(
+            receiver.as_raw_fd(),
+            &mut iov,
+            Some(&mut control),
+            MsgFlags::MSG_CMSG_CLOEXEC,
+        )
+        .expect("datagram");
+        let length = message.bytes;
+        let descriptors = message
+            .cmsgs()
+            .flat_map(|message| match message {
+                ControlMessageOwned::ScmRights(descriptors) => descriptors,
+                _ => Vec::new(),
+            })
+            // SAFETY: The kernel has just installed these descriptors for this process alone
+            .map(|descriptor| unsafe { OwnedFd::from_raw_fd(descriptor) })
+            .collect();
+        let datagram = String::from_utf8(buffer[..length].to_vec()).expect("UTF-8");
+        (datagram, descriptors)
+    }
+
+    fn arguments(socket: String) -> NotifyArguments {
+        NotifyArguments {
+            assignments: Vec::new(),
+            ready: false,
+            reloading: false,
+            stopping: false,
+            status: None,
+            pid: None,
+            booted: false,
+            barrier: false,
+            timeout: "5".parse().expect("timeout"),
+            socket,
+        }
+    }
+
+    #[test]
+    fn notify_sends_one_datagram() {
+        let (receiver, socket) = bind("datagram");
+        let arguments = NotifyArguments {
+            ready: true,
+            status: Some(String::from("Listening on port 8080")),
+            pid: Some(Some(42)),
+            assignments: vec![String::from("ERRNO=2")],
+            ..arguments(socket)
+        };
+        assert!(notify(&arguments).expect("notify"));
+        let (datagram, descriptors) = receive(&receiver);
+        assert_eq!(
+            datagram,
+            "READY=1\nSTATUS=Listening on port 8080\nMAINPID=42\nERRNO=2"
+        );
+        assert!(descriptors.is_empty());
+    }
+
+    #[test]
+    fn notify_rejects_invalid_and_missing_messages() {
+        let (_receiver, socket) = bind("invalid");
+        let invalid = NotifyArguments {
+            assignments: vec![String::from("READY")],
+            ..arguments(socket.clone())
+        };
+        assert!(matches!(notify(&invalid), Err(Error::MessageSplit(_))));
+        assert!(matches!(
+            notify(&arguments(socket)),
+            Err(Error::NotifyEmpty)
+        ));
+    }
+
+    #[test]
+    fn barrier_returns_once_the_receiver_closes_the_descriptor() {
+        let (receiver, socket) = bind("barrier");
+        let arguments = NotifyArguments {
+            ready: true,
+            barrier: true,
+            ..arguments(socket)
+        };
+        let receiving = thread::spawn(move || {
+            let (ready, _) = receive(&receiver);
+            // The descriptor is closed once the barrier has been received and dropped
+            let (barrier, descriptors) = receive(&receiver);
+            (ready, barrier, descriptors.len())
+        });
+        assert!(notify(&arguments).expect("notify"));
+        let (ready, barrier, descriptors) = receiving.join().expect("receiver");
+        assert_eq!(ready, "READY=1");
+        assert_eq!(barrier, "BARRIER=1");
+        assert_eq!(descriptors, 1);
+    }
+
+    #[test]
+    fn barrier_times_out_while_the_receiver_keeps_the_descriptor() {
+        let (receiver, socket) = bind("timeout");
+        let arguments = NotifyArguments {
+            barrier: true,
+            timeout: "100ms".parse().expect("timeout"),
+            ..arguments(socket)
+        };
+        let receiving = thread::spawn(move || receive(&receiver));
+        assert!(matches!(
+            notify(&arguments),
+            Err(Error::NotifyBarrierTimeout)
+        ));
+        let (barrier, descriptors) = receiving.join().expect("receiver");
+        assert_eq!(barrier, "BARRIER=1");
+        assert_eq!(descriptors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn barrier_waits_for_the_uds_server() {
+        let directory = TempDir::new("notify-barrier");
+        let socket = directory.join("notify").to_string_lossy().into_owned();
+        let mut config = Configuration::defaults().expect("default configuration");
+        config.notify_socket = ConfigString(socket.as_str().into());
+        let service = SharedService::default();
+        let token = CancellationToken::new();
+        let (ready_sender, mut ready_receiver) = mpsc::channel(1);
+        let (config_sender, _config_receiver) = mpsc::channel(16);
+        let (event_sender, mut event_receiver) = mpsc::channel(16);
+        let (update_sender, _) = broadcast::channel(16);
+        let server = tokio::spawn(uds::server(
+            token.clone(),
+            Arc::new(RwLock::new(config)),
+            service.clone(),
+            ready_sender,
+            config_sender,
+            event_sender,
+            update_sender,
+        ));
+        ready_receiver.recv().await.expect("ready");
+
+        let arguments = NotifyArguments {
+            ready: true,
+            status: Some(String::from("Serving")),
+            barrier: true,
+            ..arguments(socket)
+        };
+        let result = task::spawn_blocking(move || notify(&arguments))
+            .await
+            .expect("join");
+        assert!(result.expect("notify"));
+        // The barrier has returned, so the messages before it have been processed
+        assert!(matches!(event_receiver.try_recv(), Ok(Event::Ready)));
+        assert_eq!(service.read().await.status.as_deref(), Some("Serving"));
+
+        token.cancel();
+        server.await.expect("join").expect("server");
+    }
+}
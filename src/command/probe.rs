@@ -0,0 +1,261 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    os::unix::net::UnixStream,
+    time::Duration,
+};
+
+use crate::{cli::ProbeArguments, error::Error};
+
+/// Queries a probe of a running adapter and returns whether it passes
+pub fn probe(arguments: &ProbeArguments) -> Result<bool, Error> {
+    let timeout = Some(Duration::from(arguments.timeout));
+    let path = arguments.probe.path();
+
+    let (status, body) = if let Some(socket) = &arguments.socket {
+        let stream = UnixStream::connect(socket).map_err(Error::ProbeConnect)?;
+        stream
+            .set_read_timeout(timeout)
+            .map_err(Error::ProbeConnect)?;
+        stream
+            .set_write_timeout(timeout)
+            .map_err(Error::ProbeConnect)?;
+        request(stream, "localhost", path, arguments)?
+    } else {
+        let (authority, base) = parse_url(&arguments.url)?;
+        let stream = connect(authority, timeout)?;
+        request(stream, authority, &format!("{base}{path}"), arguments)?
+    };
+
+    if arguments.json {
+        println!("{body}");
+    }
+
+    Ok((200..300).contains(&status))
+}
+
+/// Splits an `http://` URL into its authority and path
+fn parse_url(url: &str) -> Result<(&str, &str), Error> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| Error::ProbeUrl(url.into()))?;
+    let (authority, base) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    if authority.is_empty() {
+        return Err(Error::ProbeUrl(url.into()));
+    }
+    Ok((authority, base.trim_end_matches('/')))
+}
+
+/// The addresses of an authority, where the port of the URL scheme applies without an explicit
+/// one, and an IPv6 address is enclosed in brackets
+fn addresses(authority: &str) -> io::Result<Vec<SocketAddr>> {
+    if let Some(address) = authority
+        .strip_prefix('[')
+        .and_then(|address| address.strip_suffix(']'))
+    {
+        (address, 80).to_socket_addrs().map(Iterator::collect)
+    } else if authority.contains(':') {
+        authority.to_socket_addrs().map(Iterator::collect)
+    } else {
+        (authority, 80).to_socket_addrs().map(Iterator::collect)
+    }
+}
+
+fn connect(authority: &str, timeout: Option<Duration>) -> Result<TcpStream, Error> {
+    let addresses = addresses(authority).map_err(Error::ProbeConnect)?;
+
+    let mut last_error = None;
+    for address in addresses {
+        let result = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+            None => TcpStream::connect(address),
+        };
+        match result {
+            Ok(stream) => {
+                stream
+                    .set_read_timeout(timeout)
+                    .map_err(Error::ProbeConnect)?;
+                stream
+                    .set_write_timeout(timeout)
+                    .map_err(Error::ProbeConnect)?;
+                return Ok(stream);
+            }
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(Error::ProbeConnect(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No address found for {authority}"),
+        )
+    })))
+}
+
+/// Sends an HTTP/1.0 request, so that the response is neither chunked nor kept alive
+fn request<S: Read + Write>(
+    mut stream: S,
+    host: &str,
+    path: &str,
+    arguments: &ProbeArguments,
+) -> Result<(u16, String), Error> {
+    let mut head = format!("GET {path} HTTP/1.0\r\nHost: {host}\r\n");
+    if arguments.json {
+        head.push_str("Accept: application/json\r\n");
+    }
+    if let Some(token) = &arguments.token {
+        head.push_str("Authorization: Bearer ");
+        head.push_str(token);
+        head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+    stream
+        .write_all(head.as_bytes())
+        .map_err(Error::ProbeRequest)?;
+
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .map_err(Error::ProbeRequest)?;
+    let response = String::from_utf8_lossy(&response);
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| Error::ProbeResponse(response.to_string()))?;
+    let status = head
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| Error::ProbeResponse(head.into()))?;
+
+    Ok((status, body.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::cli::ProbeName;
+
+    /// A connection that answers with a fixed response and records the request
+    struct Stream {
+        response: Cursor<Vec<u8>>,
+        request: Vec<u8>,
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.response.read(buffer)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.request.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn arguments(token: Option<&str>, json: bool) -> ProbeArguments {
+        ProbeArguments {
+            probe: ProbeName::Readyz,
+            url: String::from("http://127.0.0.1:8089"),
+            socket: None,
+            token: token.map(String::from),
+            timeout: "5".parse().expect("timeout"),
+            json,
+        }
+    }
+
+    fn respond(
+        response: &str,
+        arguments: &ProbeArguments,
+    ) -> (Result<(u16, String), Error>, String) {
+        let mut stream = Stream {
+            response: Cursor::new(response.as_bytes().to_vec()),
+            request: Vec::new(),
+        };
+        let result = request(&mut stream, "127.0.0.1:8089", "/readyz", arguments);
+        (result, String::from_utf8(stream.request).expect("request"))
+    }
+
+    #[test]
+    fn url_is_split_into_authority_and_base() {
+        assert_eq!(
+            parse_url("http://127.0.0.1:8089").expect("URL"),
+            ("127.0.0.1:8089", "")
+        );
+        assert_eq!(
+            parse_url("http://adapter/base/").expect("URL"),
+            ("adapter", "/base")
+        );
+        assert_eq!(
+            parse_url("http://[::1]:8089/base").expect("URL"),
+            ("[::1]:8089", "/base")
+        );
+        for url in [
+            "127.0.0.1:8089",
+            "https://adapter",
+            "http://",
+            "http:///readyz",
+        ] {
+            assert!(matches!(parse_url(url), Err(Error::ProbeUrl(_))), "{url}");
+        }
+    }
+
+    #[test]
+    fn ipv6_addresses_default_to_port_80() {
+        let address = |authority| addresses(authority).expect("address");
+        assert_eq!(address("[::1]"), ["[::1]:80".parse().expect("address")]);
+        assert_eq!(
+            address("[::1]:8089"),
+            ["[::1]:8089".parse().expect("address")]
+        );
+        assert_eq!(
+            address("127.0.0.1"),
+            ["127.0.0.1:80".parse().expect("address")]
+        );
+    }
+
+    #[test]
+    fn request_returns_status_and_body() {
+        let (result, sent) = respond(
+            "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"readyz\":true}",
+            &arguments(Some("hunter2"), true),
+        );
+        let (status, body) = result.expect("response");
+        assert_eq!(status, 200);
+        assert_eq!(body, "{\"readyz\":true}");
+        assert_eq!(
+            sent,
+            "GET /readyz HTTP/1.0\r\nHost: 127.0.0.1:8089\r\nAccept: application/json\r\n\
+             Authorization: Bearer hunter2\r\n\r\n"
+        );
+
+        let (result, sent) = respond(
+            "HTTP/1.1 503 Service Unavailable\r\n\r\n",
+            &arguments(None, false),
+        );
+        assert_eq!(result.expect("response"), (503, String::new()));
+        assert_eq!(sent, "GET /readyz HTTP/1.0\r\nHost: 127.0.0.1:8089\r\n\r\n");
+    }
+
+    #[test]
+    fn request_rejects_malformed_responses() {
+        for response in [
+            "",
+            "HTTP/1.0 200 OK\r\n",
+            "HTTP/1.0 OK\r\n\r\n",
+            "garbage\r\n\r\n",
+        ] {
+            let (result, _) = respond(response, &arguments(None, false));
+            assert!(
+                matches!(result, Err(Error::ProbeResponse(_))),
+                "{response:?}"
+            );
+        }
+    }
+}
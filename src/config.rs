@@ -1,16 +1,40 @@
-use std::{path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    env, fmt,
+    ops::Add,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
-use derive_more::{Add, FromStr};
 use envconfig::Envconfig;
+use nix::sys::signal::Signal;
+use subtle::ConstantTimeEq;
 use tokio::sync::{
     mpsc::{Receiver, Sender},
     RwLock,
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{info, instrument, warn};
-use valuable::Valuable;
+use tracing::{error, info, instrument, warn};
+use tracing_subscriber::{filter::LevelFilter, reload, Registry};
+use valuable::{Fields, NamedValues, Structable, Valuable, Value, Visit};
 
-use crate::{error::Error, event::EventList};
+use crate::{
+    error::Error,
+    event::{EventCodes, EventList},
+    message::NotifyAccess,
+    server::{
+        admin::ListenAddress,
+        http::{ResponseCodes, ResponseFormat},
+    },
+    shutdown::ShutdownWait,
+    signals::SignalActions,
+    source::{file, unit},
+    supervisor::Restart,
+    timer::watchdog,
+    validation::{self, Problem, Severity},
+};
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Envconfig, Valuable)]
@@ -24,6 +48,8 @@ pub struct Configuration {
     pub echo: bool,
     #[envconfig(from = "ADAPTER_LOG", default = "true")]
     pub log: bool,
+    #[envconfig(from = "ADAPTER_LOG_FILE")]
+    pub log_file: Option<ConfigString>,
     #[envconfig(from = "ADAPTER_CHANNEL_SIZE", default = "32")]
     pub channel_size: usize,
     #[envconfig(from = "ADAPTER_INITIAL_LIVEZ", default = "false")]
@@ -34,6 +60,27 @@ pub struct Configuration {
     pub allow_message_watchdog_usec: bool,
     #[envconfig(from = "ADAPTER_ALLOW_MESSAGE_EXTEND_TIMEOUT_USEC", default = "true")]
     pub allow_message_extend_timeout_usec: bool,
+    #[envconfig(from = "ADAPTER_ADMIN_ADDRESS", default = "127.0.0.1:8090")]
+    pub admin_address: ListenAddress,
+    #[envconfig(from = "ADAPTER_ADMIN_TOKEN")]
+    pub admin_token: Option<ConfigSecret>,
+    #[envconfig(from = "ADAPTER_ADMIN_NOTIFY", default = "false")]
+    pub admin_notify: bool,
+    #[envconfig(from = "ADAPTER_GRPC_PORT")]
+    pub grpc_port: Option<u16>,
+    // HTTP response configuration
+    #[envconfig(from = "ADAPTER_HTTP_HEALTHZ_CODES", default = "pass=200,fail=503")]
+    pub http_healthz_codes: ResponseCodes,
+    #[envconfig(from = "ADAPTER_HTTP_HEALTHZ_FORMAT", default = "json")]
+    pub http_healthz_format: ResponseFormat,
+    #[envconfig(from = "ADAPTER_HTTP_LIVEZ_CODES", default = "pass=200,fail=503")]
+    pub http_livez_codes: ResponseCodes,
+    #[envconfig(from = "ADAPTER_HTTP_LIVEZ_FORMAT", default = "json")]
+    pub http_livez_format: ResponseFormat,
+    #[envconfig(from = "ADAPTER_HTTP_READYZ_CODES", default = "pass=200,fail=503")]
+    pub http_readyz_codes: ResponseCodes,
+    #[envconfig(from = "ADAPTER_HTTP_READYZ_FORMAT", default = "json")]
+    pub http_readyz_format: ResponseFormat,
     // Status change configuration
     #[envconfig(from = "ADAPTER_STATUS_LIVEZ_TRUE", default = "ready,watchdog")]
     pub status_livez_true: EventList,
@@ -46,26 +93,341 @@ pub struct Configuration {
     pub status_readyz_true: EventList,
     #[envconfig(
         from = "ADAPTER_STATUS_READYZ_FALSE",
-        default = "reloading,stopping,errno,buserror,watchdog_trigger,watchdog_timeout,start_timeout"
+        default = "reloading,stopping,errno,buserror,watchdog_trigger,watchdog_timeout,start_timeout,exit"
     )]
     pub status_readyz_false: EventList,
     #[envconfig(from = "ADAPTER_STATUS_SHUTDOWN", default = "")]
     pub status_shutdown: EventList,
+    #[envconfig(from = "ADAPTER_STATUS_SHUTDOWN_CODES", default = "")]
+    pub status_shutdown_codes: EventCodes,
+    // Shutdown configuration
+    #[envconfig(from = "ADAPTER_SHUTDOWN_DRAIN_SEC", default = "0")]
+    pub shutdown_drain_sec: Seconds,
+    #[envconfig(from = "ADAPTER_SHUTDOWN_WAIT", default = "none")]
+    pub shutdown_wait: ShutdownWait,
+    // Signal configuration
+    #[envconfig(
+        from = "ADAPTER_SIGNAL_ACTIONS",
+        default = "SIGHUP=reload,SIGINT=shutdown,SIGQUIT=shutdown,SIGTERM=drain,SIGALRM=ignore,SIGPIPE=ignore,SIGUSR1=dump,SIGUSR2=ignore"
+    )]
+    pub signal_actions: SignalActions,
+    // Job configuration
+    #[envconfig(from = "ADAPTER_JOB", default = "false")]
+    pub job: bool,
+    #[envconfig(from = "ADAPTER_JOB_SILENCE_SEC", default = "infinity")]
+    pub job_silence_sec: Seconds,
+    // Kubernetes configuration
+    #[envconfig(
+        from = "ADAPTER_KUBERNETES_API_URL",
+        default = "https://kubernetes.default.svc"
+    )]
+    pub kubernetes_api_url: ConfigString,
+    #[envconfig(
+        from = "ADAPTER_KUBERNETES_TOKEN_FILE",
+        default = "/var/run/secrets/kubernetes.io/serviceaccount/token"
+    )]
+    pub kubernetes_token_file: ConfigString,
+    #[envconfig(
+        from = "ADAPTER_KUBERNETES_CA_FILE",
+        default = "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt"
+    )]
+    pub kubernetes_ca_file: ConfigString,
+    #[envconfig(from = "ADAPTER_KUBERNETES_POD_NAME")]
+    pub kubernetes_pod_name: Option<ConfigString>,
+    #[envconfig(from = "ADAPTER_KUBERNETES_POD_NAMESPACE")]
+    pub kubernetes_pod_namespace: Option<ConfigString>,
+    #[envconfig(from = "ADAPTER_KUBERNETES_POD_UID")]
+    pub kubernetes_pod_uid: Option<ConfigString>,
+    #[envconfig(from = "ADAPTER_KUBERNETES_CONDITION")]
+    pub kubernetes_condition: Option<ConfigString>,
+    #[envconfig(from = "ADAPTER_KUBERNETES_BATCH_SEC", default = "1")]
+    pub kubernetes_batch_sec: Seconds,
+    #[envconfig(from = "ADAPTER_KUBERNETES_EVENTS", default = "")]
+    pub kubernetes_events: EventList,
+    #[envconfig(from = "ADAPTER_KUBERNETES_EVENT_INTERVAL_SEC", default = "60")]
+    pub kubernetes_event_interval_sec: Seconds,
     // `systemd` unit configuration
     #[envconfig(from = "ADAPTER_UNIT_TIMEOUT_START_SEC", default = "90")]
     pub unit_timeout_start_sec: Seconds,
     #[envconfig(from = "ADAPTER_UNIT_WATCHDOG_SEC", default = "0")]
     pub unit_watchdog_sec: Seconds,
+    #[envconfig(from = "ADAPTER_UNIT_TIMEOUT_STOP_SEC", default = "90")]
+    pub unit_timeout_stop_sec: Seconds,
+    #[envconfig(from = "ADAPTER_UNIT_WATCHDOG_SIGNAL", default = "SIGABRT")]
+    pub unit_watchdog_signal: ConfigSignal,
+    #[envconfig(from = "ADAPTER_UNIT_TIMEOUT_ABORT_SEC")]
+    pub unit_timeout_abort_sec: Option<Seconds>,
+    #[envconfig(from = "ADAPTER_UNIT_RUNTIME_MAX_SEC", default = "infinity")]
+    pub unit_runtime_max_sec: Seconds,
+    #[envconfig(from = "ADAPTER_UNIT_NOTIFY_ACCESS", default = "all")]
+    pub unit_notify_access: NotifyAccess,
+    #[envconfig(from = "ADAPTER_UNIT_RESTART", default = "no")]
+    pub unit_restart: Restart,
+    #[envconfig(from = "ADAPTER_UNIT_RESTART_SEC", default = "100ms")]
+    pub unit_restart_sec: Seconds,
+    #[envconfig(from = "ADAPTER_UNIT_START_LIMIT_BURST", default = "5")]
+    pub unit_start_limit_burst: u32,
+    #[envconfig(from = "ADAPTER_UNIT_START_LIMIT_INTERVAL_SEC", default = "10s")]
+    pub unit_start_limit_interval_sec: Seconds,
+}
+
+/// Declares the keys that only take effect when the adapter starts, along with keeping them at
+/// their current values on reload, so that the two cannot disagree
+macro_rules! startup_keys {
+    ($($key: ident),* $(,)?) => {
+        pub const STARTUP_KEYS: &[&str] = &[$(stringify!($key)),*];
+
+        impl Configuration {
+            fn keep_startup_keys(&self, reloaded: &mut Configuration) {
+                $(reloaded.$key.clone_from(&self.$key);)*
+            }
+        }
+    };
+}
+
+startup_keys!(
+    notify_socket,
+    port,
+    log_file,
+    channel_size,
+    initial_livez,
+    initial_readyz,
+    admin_address,
+    admin_notify,
+    grpc_port,
+    job,
+    kubernetes_api_url,
+    kubernetes_token_file,
+    kubernetes_ca_file,
+    kubernetes_pod_name,
+    kubernetes_pod_namespace,
+    kubernetes_pod_uid,
+    kubernetes_condition,
+    unit_runtime_max_sec,
+);
+
+impl Configuration {
+    /// Loads the configuration from command-line options, the environment, a configuration
+    /// file, a unit file, and the defaults, in that order of precedence, along with warnings
+    /// about the unit file
+    pub fn load(
+        file: Option<&Path>,
+        unit_file: Option<&Path>,
+        options: &[(String, String)],
+    ) -> Result<(Self, Vec<Problem>), Error> {
+        let mut variables = HashMap::new();
+        // Where variables not from the environment were set, for error messages
+        let mut origins = HashMap::new();
+        let mut problems = Vec::new();
+
+        if let Some(path) = unit_file {
+            let (entries, warnings) = unit::read(path)?;
+            for entry in entries {
+                let variable = variable(&entry.key);
+                let origin = Error::UnitFileValue(entry.path, entry.line, entry.directive);
+                origins.insert(variable.clone(), origin);
+                variables.insert(variable, entry.value);
+            }
+            problems.extend(warnings.into_iter().map(validation::warning));
+        }
+
+        if let Some(path) = file {
+            let keys = Self::keys()?;
+            for entry in file::read(path)? {
+                if !keys.contains(&entry.key) {
+                    return Err(Error::ConfigFileKey(path.into(), entry.line, entry.key));
+                }
+                let variable = variable(&entry.key);
+                let origin = Error::ConfigFileValue(path.into(), entry.line, entry.key);
+                origins.insert(variable.clone(), origin);
+                variables.insert(variable, entry.value);
+            }
+        }
+
+        // Like `init_from_env`, which treats variables that are not Unicode as unset
+        for (variable, value) in env::vars_os() {
+            if let (Ok(variable), Ok(value)) = (variable.into_string(), value.into_string()) {
+                origins.remove(&variable);
+                variables.insert(variable, value);
+            }
+        }
+
+        for (key, value) in options {
+            let variable = variable(key);
+            origins.insert(variable.clone(), Error::ConfigOption(key.replace('_', "-")));
+            variables.insert(variable, value.clone());
+        }
+
+        let config = Self::init_from_hashmap(&variables).map_err(|error| match error {
+            envconfig::Error::ParseError { name } if origins.contains_key(name) => {
+                origins.remove(name).unwrap_or(Error::Config(error))
+            }
+            error => Error::Config(error),
+        })?;
+        Ok((config, problems))
+    }
+
+    /// The configuration with every key at its default
+    pub fn defaults() -> Result<Self, Error> {
+        Self::init_from_hashmap(&HashMap::new()).map_err(Error::Config)
+    }
+
+    /// The keys of the configuration file, which are the names of the fields
+    pub fn keys() -> Result<Vec<String>, Error> {
+        let defaults = Self::defaults()?;
+        let definition = defaults.definition();
+        let Fields::Named(fields) = definition.fields() else {
+            return Ok(Vec::new());
+        };
+        Ok(fields.iter().map(|field| field.name().into()).collect())
+    }
+
+    /// Applies a reloaded configuration, except for the keys in `STARTUP_KEYS`, and returns the
+    /// keys that have changed
+    fn reload(&mut self, mut reloaded: Configuration) -> Vec<String> {
+        let current = self.values();
+        let mut changed = Vec::new();
+        for ((key, from), (_, to)) in current.iter().zip(reloaded.values()) {
+            // Secrets are compared by their values, which are redacted in the log
+            let secret = |config: &Configuration| {
+                config
+                    .secrets()
+                    .into_iter()
+                    .find(|(secret, _)| secret == key)
+                    .and_then(|(_, value)| value.map(|value| value.0.clone()))
+            };
+            if *from == to && secret(self) == secret(&reloaded) {
+                continue;
+            }
+            if STARTUP_KEYS.contains(&key.as_str()) {
+                warn!(%key, %from, %to, "Configuration change requires a restart");
+            } else {
+                info!(%key, %from, %to, "Configuration changed");
+                changed.push(key.clone());
+            }
+        }
+
+        self.keep_startup_keys(&mut reloaded);
+        *self = reloaded;
+
+        changed
+    }
+
+    /// The secrets of the configuration by key, which are redacted in its `Valuable` values
+    pub fn secrets(&self) -> [(&'static str, Option<&ConfigSecret>); 1] {
+        [("admin_token", self.admin_token.as_ref())]
+    }
+
+    /// The values of the configuration by key, formatted for comparison and logging
+    fn values(&self) -> Vec<(String, String)> {
+        struct Values(Vec<(String, String)>);
+        impl Visit for Values {
+            fn visit_value(&mut self, _: Value<'_>) {}
+
+            fn visit_named_fields(&mut self, named_values: &NamedValues<'_>) {
+                for (field, value) in named_values {
+                    self.0.push((field.name().into(), format!("{value:?}")));
+                }
+            }
+        }
+
+        let mut values = Values(Vec::new());
+        self.visit(&mut values);
+        values.0
+    }
+}
+
+/// Loads the configuration again from the sources it was loaded from, for the `reload` signal
+/// action and `POST /reload`
+#[derive(Clone)]
+pub struct Reloader {
+    config_file: Option<Arc<Path>>,
+    unit_file: Option<Arc<Path>>,
+    options: Arc<[(String, String)]>,
+    config_sender: Sender<ConfigurationChange>,
+}
+
+impl Reloader {
+    pub fn new(
+        config_file: Option<&Path>,
+        unit_file: Option<&Path>,
+        options: &[(String, String)],
+        config_sender: Sender<ConfigurationChange>,
+    ) -> Self {
+        Reloader {
+            config_file: config_file.map(Into::into),
+            unit_file: unit_file.map(Into::into),
+            options: options.into(),
+            config_sender,
+        }
+    }
+
+    /// Reloads the configuration, leaving the current one in place if the new one cannot be
+    /// loaded or has errors, which are returned
+    pub async fn reload(&self) -> Result<Vec<String>, Error> {
+        info!("Reloading configuration");
+        let (reloaded, mut problems) = match Configuration::load(
+            self.config_file.as_deref(),
+            self.unit_file.as_deref(),
+            &self.options,
+        ) {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                error!("Could not reload configuration: {}", error);
+                return Ok(vec![error.to_string()]);
+            }
+        };
+        problems.extend(validation::validate(&reloaded));
+        let mut errors = Vec::new();
+        for problem in problems {
+            match problem.severity {
+                Severity::Warning => warn!("{}", problem.message),
+                Severity::Error => {
+                    error!("Could not reload configuration: {}", problem.message);
+                    errors.push(problem.message);
+                }
+            }
+        }
+        if errors.is_empty() {
+            self.config_sender
+                .send(ConfigurationChange::Reload(Box::new(reloaded)))
+                .await
+                .map_err(Error::ConfigChannelSend)?;
+        }
+        Ok(errors)
+    }
+}
+
+/// The environment variable of a configuration key
+pub fn variable(key: &str) -> String {
+    if key == "notify_socket" {
+        String::from("NOTIFY_SOCKET")
+    } else {
+        format!("ADAPTER_{}", key.to_uppercase())
+    }
 }
 
 pub type SharedConfiguration = Arc<RwLock<Configuration>>;
 
+/// Changes the level of logging when the configuration is reloaded
+pub type LogHandle = reload::Handle<LevelFilter, Registry>;
+
+pub fn log_filter(log: bool) -> LevelFilter {
+    if log {
+        LevelFilter::INFO
+    } else {
+        LevelFilter::OFF
+    }
+}
+
 #[instrument(name = "Config writer", skip_all)]
 pub async fn config_writer(
     token: CancellationToken,
     config: SharedConfiguration,
     mut config_receiver: Receiver<ConfigurationChange>,
     ready_sender: Sender<()>,
+    watchdog_sender: Sender<watchdog::Message>,
+    log_handle: LogHandle,
 ) -> Result<(), Error> {
     info!("Config writer ready");
     ready_sender
@@ -80,10 +442,12 @@ pub async fn config_writer(
         }
         .ok_or(Error::ConfigChannelClosed)?;
         let mut config_lock = config.write().await;
+        let mut watchdog_changed = false;
         match change {
             ConfigurationChange::WatchdogTimeout(timeout) => {
                 if config_lock.allow_message_watchdog_usec {
                     config_lock.unit_watchdog_sec = timeout;
+                    watchdog_changed = true;
                 } else {
                     warn!("Attempted to override watchdog timeout, but ADAPTER_ALLOW_MESSAGE_WATCHDOG_USEC is false");
                 }
@@ -95,6 +459,33 @@ pub async fn config_writer(
                     warn!("Attempted to override startup timeout, but ADAPTER_ALLOW_MESSAGE_EXTEND_TIMEOUT_USEC is false");
                 }
             }
+            ConfigurationChange::NotifyAccess(access) => {
+                config_lock.unit_notify_access = access;
+            }
+            ConfigurationChange::Reload(reloaded) => {
+                // Logging is enabled before and disabled after reporting the changes
+                if reloaded.log {
+                    log_handle
+                        .reload(log_filter(true))
+                        .map_err(Error::LogReload)?;
+                }
+                let changed = config_lock.reload(*reloaded);
+                watchdog_changed = changed.iter().any(|key| key == "unit_watchdog_sec");
+                info!(config = config_lock.as_value(), "Reloaded configuration");
+                if !config_lock.log {
+                    log_handle
+                        .reload(log_filter(false))
+                        .map_err(Error::LogReload)?;
+                }
+            }
+        }
+        // The watchdog timer reads its timeout once it is told about a change
+        drop(config_lock);
+        if watchdog_changed {
+            watchdog_sender
+                .send(watchdog::Message::NewTimeout)
+                .await
+                .map_err(Error::WatchdogChannelSend)?;
         }
     }
 
@@ -106,14 +497,167 @@ pub async fn config_writer(
 pub enum ConfigurationChange {
     WatchdogTimeout(Seconds),
     StartupTimeout(Seconds),
+    NotifyAccess(NotifyAccess),
+    Reload(Box<Configuration>),
 }
 
-#[derive(FromStr, Clone, Copy, Add, Valuable, PartialEq)]
-pub struct Seconds(pub f64);
+/// A span of time like systemd.time(7), e.g. `90`, `1min 30s`, `500ms` or `infinity`, with a
+/// resolution of microseconds
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Seconds(pub Duration);
+
+/// The units of time spans and their length in microseconds
+const TIME_UNITS: [(&[&str], u128); 9] = [
+    (&["usec", "us", "µs", "μs"], 1),
+    (&["msec", "ms"], 1_000),
+    (&["seconds", "second", "sec", "s", ""], 1_000_000),
+    (&["minutes", "minute", "min", "m"], 60_000_000),
+    (&["hours", "hour", "hr", "h"], 3_600_000_000),
+    (&["days", "day", "d"], 86_400_000_000),
+    (&["weeks", "week", "w"], 604_800_000_000),
+    (&["months", "month", "M"], 2_629_800_000_000),
+    (&["years", "year", "y"], 31_557_600_000_000),
+];
+
+impl Seconds {
+    pub const ZERO: Self = Seconds(Duration::ZERO);
+    pub const INFINITY: Self = Seconds(Duration::MAX);
+
+    /// Converts microseconds of a message, where `u64::MAX` is infinity like `USEC_INFINITY`
+    pub fn from_microseconds(microseconds: u64) -> Self {
+        if microseconds == u64::MAX {
+            Self::INFINITY
+        } else {
+            Seconds(Duration::from_micros(microseconds))
+        }
+    }
+
+    pub fn as_microseconds(self) -> u64 {
+        if self == Self::INFINITY {
+            u64::MAX
+        } else {
+            u64::try_from(self.0.as_micros()).unwrap_or(u64::MAX)
+        }
+    }
+}
+
+impl FromStr for Seconds {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || Error::ParseSeconds(s.into());
+        let mut rest = s.trim();
+        if rest == "infinity" {
+            return Ok(Self::INFINITY);
+        }
+        if rest.is_empty() {
+            return Err(error());
+        }
+
+        let mut microseconds: u128 = 0;
+        while !rest.is_empty() {
+            let (number, tail) = rest.split_at(
+                rest.find(|c: char| !c.is_ascii_digit() && c != '.')
+                    .unwrap_or(rest.len()),
+            );
+            let tail = tail.trim_start();
+            let (unit, tail) = tail.split_at(
+                tail.find(|c: char| !c.is_alphabetic())
+                    .unwrap_or(tail.len()),
+            );
+            let (_, length) = TIME_UNITS
+                .iter()
+                .find(|(names, _)| names.contains(&unit))
+                .ok_or_else(error)?;
+            let span = scale(number, *length).ok_or_else(error)?;
+            microseconds = microseconds.checked_add(span).ok_or_else(error)?;
+            rest = tail.trim_start();
+        }
+
+        let seconds = u64::try_from(microseconds / 1_000_000).map_err(|_| error())?;
+        #[allow(clippy::cast_possible_truncation)]
+        let nanoseconds = (microseconds % 1_000_000) as u32 * 1_000;
+        Ok(Seconds(Duration::new(seconds, nanoseconds)))
+    }
+}
+
+/// Multiplies a decimal number by the length of its unit in microseconds, without rounding
+/// through floating point
+fn scale(number: &str, length: u128) -> Option<u128> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let digits = |digits: &str| {
+        if digits.is_empty() {
+            Some(0)
+        } else if digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            digits.parse::<u128>().ok()
+        } else {
+            None
+        }
+    };
+    // Digits beyond the resolution of microseconds cannot change the result
+    let fraction = &fraction[..fraction.len().min(18)];
+    let whole = digits(whole)?.checked_mul(length)?;
+    let fraction = digits(fraction)? * length / 10u128.pow(u32::try_from(fraction.len()).ok()?);
+    whole.checked_add(fraction)
+}
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Self::INFINITY {
+            return f.write_str("infinity");
+        }
+        let mut microseconds = self.0.as_micros();
+        if microseconds == 0 {
+            return f.write_str("0");
+        }
+        let mut parts = Vec::new();
+        for (unit, length) in [
+            ("d", 86_400_000_000),
+            ("h", 3_600_000_000),
+            ("min", 60_000_000),
+            ("s", 1_000_000),
+            ("ms", 1_000),
+            ("us", 1),
+        ] {
+            if microseconds >= length {
+                parts.push(format!("{}{unit}", microseconds / length));
+                microseconds %= length;
+            }
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+impl Add for Seconds {
+    type Output = Self;
+
+    /// Infinity stays infinite
+    fn add(self, rhs: Self) -> Self::Output {
+        Seconds(self.0.saturating_add(rhs.0))
+    }
+}
 
 impl From<Seconds> for Duration {
     fn from(value: Seconds) -> Self {
-        Duration::from_secs_f64(value.0)
+        value.0
+    }
+}
+
+/// Written to logs as a number of seconds
+impl Valuable for Seconds {
+    fn as_value(&self) -> valuable::Value<'_> {
+        if *self == Self::INFINITY {
+            valuable::Value::F64(f64::INFINITY)
+        } else {
+            valuable::Value::F64(self.0.as_secs_f64())
+        }
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
     }
 }
 
@@ -143,3 +687,220 @@ impl From<ConfigString> for PathBuf {
         PathBuf::from(value.0.as_ref())
     }
 }
+
+/// A configuration value that is never written to logs
+#[derive(Clone)]
+pub struct ConfigSecret(pub Arc<str>);
+impl Valuable for ConfigSecret {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String("<redacted>")
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+impl FromStr for ConfigSecret {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ConfigSecret(String::from(s).into()))
+    }
+}
+
+impl ConfigSecret {
+    /// Whether a provided value is the secret, in constant time so that it cannot be guessed
+    /// byte by byte
+    pub fn matches(&self, provided: &str) -> bool {
+        provided.as_bytes().ct_eq(self.0.as_bytes()).into()
+    }
+}
+
+/// A signal by name, like `SIGABRT` or `ABRT`, or by number
+#[derive(Clone, Copy)]
+pub struct ConfigSignal(pub Signal);
+impl Valuable for ConfigSignal {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(self.0.as_str())
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+impl FromStr for ConfigSignal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let signal = match s.parse::<i32>() {
+            Ok(number) => Signal::try_from(number),
+            Err(_) if s.starts_with("SIG") => s.parse(),
+            Err(_) => format!("SIG{s}").parse(),
+        };
+        signal
+            .map(ConfigSignal)
+            .map_err(|_| Error::ParseSignal(s.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(settings: &[(&str, &str)]) -> Configuration {
+        let variables = settings
+            .iter()
+            .map(|(key, value)| (variable(key), (*value).into()))
+            .collect();
+        Configuration::init_from_hashmap(&variables).expect("valid configuration")
+    }
+
+    fn value(config: &Configuration, key: &str) -> String {
+        config
+            .values()
+            .into_iter()
+            .find(|(other, _)| other == key)
+            .map(|(_, value)| value)
+            .expect("known key")
+    }
+
+    fn seconds(s: &str) -> Result<Duration, Error> {
+        s.parse::<Seconds>().map(Duration::from)
+    }
+
+    #[test]
+    fn seconds_parse_bare_numbers() {
+        assert_eq!(seconds("90").ok(), Some(Duration::from_secs(90)));
+        assert_eq!(seconds("0").ok(), Some(Duration::ZERO));
+        assert_eq!(seconds("1.5").ok(), Some(Duration::from_millis(1500)));
+        assert_eq!(seconds(".25").ok(), Some(Duration::from_millis(250)));
+        assert_eq!(seconds(" 7 ").ok(), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn seconds_parse_units() {
+        assert_eq!(seconds("500ms").ok(), Some(Duration::from_millis(500)));
+        assert_eq!(seconds("20us").ok(), Some(Duration::from_micros(20)));
+        assert_eq!(seconds("3 sec").ok(), Some(Duration::from_secs(3)));
+        assert_eq!(seconds("2min").ok(), Some(Duration::from_mins(2)));
+        assert_eq!(seconds("2 minutes").ok(), Some(Duration::from_mins(2)));
+        assert_eq!(seconds("1h").ok(), Some(Duration::from_hours(1)));
+        assert_eq!(seconds("1d").ok(), Some(Duration::from_hours(24)));
+        assert_eq!(seconds("1w").ok(), Some(Duration::from_hours(168)));
+        assert_eq!(seconds("1M").ok(), Some(Duration::from_mins(43_830)));
+        assert_eq!(seconds("1y").ok(), Some(Duration::from_hours(8_766)));
+        assert_eq!(seconds("0.5min").ok(), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn seconds_parse_combined_units() {
+        assert_eq!(seconds("1min 30s").ok(), Some(Duration::from_secs(90)));
+        assert_eq!(seconds("1min30s").ok(), Some(Duration::from_secs(90)));
+        assert_eq!(
+            seconds("1h 2min 3s 4ms 5us").ok(),
+            Some(Duration::new(3_723, 4_005_000))
+        );
+        assert_eq!(seconds("1s 1s").ok(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn seconds_parse_infinity() {
+        assert!("infinity".parse::<Seconds>().ok() == Some(Seconds::INFINITY));
+        assert!(" infinity ".parse::<Seconds>().ok() == Some(Seconds::INFINITY));
+        assert_eq!(Seconds::INFINITY.as_microseconds(), u64::MAX);
+        assert!(Seconds::from_microseconds(u64::MAX) == Seconds::INFINITY);
+    }
+
+    #[test]
+    fn seconds_reject_invalid_input() {
+        for invalid in [
+            "",
+            " ",
+            "-1",
+            "1.2.3",
+            ".",
+            "abc",
+            "5 parsecs",
+            "1 min s",
+            "inf",
+            "1e3",
+            "ms",
+        ] {
+            assert!(seconds(invalid).is_err(), "{invalid:?}");
+        }
+        assert!(seconds("99999999999999999999999y").is_err());
+    }
+
+    #[test]
+    fn seconds_display_round_trips() {
+        for (source, display) in [
+            ("0", "0"),
+            ("90", "1min 30s"),
+            ("1.5", "1s 500ms"),
+            ("1d 2h", "1d 2h"),
+            ("20us", "20us"),
+            ("infinity", "infinity"),
+        ] {
+            let parsed: Seconds = source.parse().expect("valid time span");
+            assert_eq!(parsed.to_string(), display);
+            assert!(display.parse::<Seconds>().ok() == Some(parsed));
+        }
+    }
+
+    #[test]
+    fn reload_keeps_startup_keys() {
+        let changes = [
+            ("notify_socket", "/tmp/other.sock"),
+            ("port", "9000"),
+            ("log_file", "/tmp/adapter.log"),
+            ("channel_size", "64"),
+            ("initial_livez", "true"),
+            ("initial_readyz", "true"),
+            ("admin_address", "127.0.0.1:9090"),
+            ("admin_notify", "true"),
+            ("grpc_port", "9091"),
+            ("job", "true"),
+            ("kubernetes_api_url", "http://127.0.0.1:8001"),
+            ("kubernetes_token_file", "/tmp/token"),
+            ("kubernetes_ca_file", "/tmp/ca.crt"),
+            ("kubernetes_pod_name", "web-0"),
+            ("kubernetes_pod_namespace", "default"),
+            ("kubernetes_pod_uid", "0b7d0c4e"),
+            ("kubernetes_condition", "example.com/Ready"),
+            ("unit_runtime_max_sec", "1h"),
+        ];
+        assert_eq!(changes.map(|(key, _)| key).as_slice(), STARTUP_KEYS);
+
+        let mut current = config(&[]);
+        let mut settings = changes.to_vec();
+        settings.push(("echo", "false"));
+        let reloaded = config(&settings);
+        for (key, _) in changes {
+            assert_ne!(value(&current, key), value(&reloaded, key), "{key}");
+        }
+
+        let before = config(&[]);
+        assert_eq!(current.reload(reloaded), ["echo"]);
+        for (key, _) in changes {
+            assert_eq!(value(&current, key), value(&before, key), "{key}");
+        }
+        assert!(!current.echo);
+    }
+
+    #[test]
+    fn reload_compares_secrets() {
+        let mut current = config(&[("admin_token", "one")]);
+        assert_eq!(
+            current.reload(config(&[("admin_token", "two")])),
+            ["admin_token"]
+        );
+        assert_eq!(
+            current.admin_token.as_ref().map(|token| &*token.0),
+            Some("two")
+        );
+        assert!(current.reload(config(&[("admin_token", "two")])).is_empty());
+        assert_eq!(current.reload(config(&[])), ["admin_token"]);
+    }
+}
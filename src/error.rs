@@ -1,8 +1,4 @@
-use std::{
-    io,
-    num::{ParseFloatError, ParseIntError},
-    str::Utf8Error,
-};
+use std::{io, num::ParseIntError, path::PathBuf, str::Utf8Error};
 
 use thiserror::Error;
 use tokio::{sync::mpsc::error::SendError, task::JoinError};
@@ -10,12 +6,48 @@ use tracing::subscriber::SetGlobalDefaultError;
 
 use crate::{config::ConfigurationChange, event::Event, status::Change, timer::watchdog::Message};
 
+/// The adapter has failed in a way that has no more specific exit code
+pub const EXIT_FAILURE: i32 = 1;
+/// The configuration could not be loaded or is invalid
+pub const EXIT_CONFIG: i32 = 2;
+/// An event in `ADAPTER_STATUS_SHUTDOWN` has shut down the adapter, unless
+/// `ADAPTER_STATUS_SHUTDOWN_CODES` sets another code for it
+pub const EXIT_EVENT: i32 = 3;
+/// A socket could not be set up or has failed
+pub const EXIT_SOCKET: i32 = 4;
+/// An internal channel has failed
+pub const EXIT_CHANNEL: i32 = 5;
+/// The adapter has run for longer than `ADAPTER_UNIT_RUNTIME_MAX_SEC`
+pub const EXIT_RUNTIME: i32 = 6;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Could not subscribe to tracing: {0}")]
     TraceSubscribe(SetGlobalDefaultError),
     #[error("Could not load configuration: {0}")]
     Config(envconfig::Error),
+    #[error("Could not read configuration file {}: {1}", .0.display())]
+    ConfigFileRead(PathBuf, io::Error),
+    #[error("Could not parse configuration file {}: {1}", .0.display())]
+    ConfigFileParse(PathBuf, toml::de::Error),
+    #[error("Unrecognized key in configuration file {}:{1}: {2}", .0.display())]
+    ConfigFileKey(PathBuf, usize, String),
+    #[error("Could not parse value in configuration file {}:{1}: {2}", .0.display())]
+    ConfigFileValue(PathBuf, usize, String),
+    #[error("Could not read unit file {}: {1}", .0.display())]
+    UnitFileRead(PathBuf, io::Error),
+    #[error("Could not parse value in unit file {}:{1}: {2}=", .0.display())]
+    UnitFileValue(PathBuf, usize, String),
+    #[error("Could not parse value of option --{0}")]
+    ConfigOption(String),
+    #[error("Could not write the configuration as TOML: {0}")]
+    ConfigDumpToml(toml::ser::Error),
+    #[error("Could not write the configuration as JSON: {0}")]
+    ConfigDumpJson(serde_json::Error),
+    #[error("Could not change the level of logging: {0}")]
+    LogReload(tracing_subscriber::reload::Error),
+    #[error("Could not open log file {}: {1}", .0.display())]
+    LogFileOpen(PathBuf, io::Error),
     #[error("The configuration change could not be sent: {0}")]
     ConfigChannelSend(SendError<ConfigurationChange>),
     #[error("The configuration channel has closed")]
@@ -33,23 +65,41 @@ pub enum Error {
     #[error("The event channel has closed")]
     EventChannelClosed,
     #[error("An event has initiated shutdown: {0:?}")]
-    EventShutdown(Event),
+    EventShutdown(Event, i32),
     #[error("The shutdown channel has closed")]
     ShutdownChannelClosed,
     #[error("The ready could not be sent: {0}")]
     ReadyChannelSend(SendError<()>),
     #[error("Could not parse unrecognized event: {0}")]
     ParseEvent(String),
+    #[error("Could not parse unrecognized condition: {0}")]
+    ParseCondition(String),
+    #[error("Could not parse exit codes from: {0}")]
+    ParseEventCodes(String),
+    #[error("Could not parse status codes from: {0}")]
+    ParseResponseCodes(String),
+    #[error("Could not parse unrecognized response format: {0}")]
+    ParseResponseFormat(String),
     #[error("The provided value of NOTIFYACCESS is not supported: {0}")]
     ParseNotifyAccess(String),
-    #[error("Could not parse number of seconds from: {0}")]
-    ParseSeconds(ParseFloatError),
+    #[error("Could not parse unrecognized restart policy: {0}")]
+    ParseRestart(String),
+    #[error("Could not parse unrecognized shutdown wait: {0}")]
+    ParseShutdownWait(String),
+    #[error("Could not parse unrecognized signal: {0}")]
+    ParseSignal(String),
+    #[error("Could not parse signal actions from: {0}")]
+    ParseSignalActions(String),
+    #[error("Could not parse time span from: {0}")]
+    ParseSeconds(String),
     #[error("The UDS server could not delete a pre-existing socket: {0}")]
     UdsDeleteSocket(io::Error),
     #[error("The UDS server could not create a new socket: {0}")]
     UdsCreateSocket(io::Error),
     #[error("The UDS server could not get a socket option: {0}")]
     UdsGetSocketOption(nix::errno::Errno),
+    #[error("The UDS server could not set a socket option: {0}")]
+    UdsSetSocketOption(nix::errno::Errno),
     #[error("The UDS server could not receive a datagram")]
     UdsReceiveDatagram(io::Error),
     #[error("The UDS server could not decode the datagram into UTF-8")]
@@ -60,16 +110,134 @@ pub enum Error {
     HttpBindAddress(io::Error),
     #[error("The HTTP server encountered an error: {0}")]
     Http(io::Error),
+    #[error("The admin server could not bind to the address: {0}")]
+    AdminBindAddress(io::Error),
+    #[error("The admin server encountered an error: {0}")]
+    Admin(io::Error),
+    #[error("Could not parse listen address from: {0}")]
+    ParseListenAddress(String),
+    #[error("The gRPC server could not bind to the address: {0}")]
+    GrpcBindAddress(io::Error),
+    #[error("The gRPC server encountered an error: {0}")]
+    Grpc(tonic::transport::Error),
+    #[error("Could not parse HTTP URL from: {0}")]
+    ProbeUrl(String),
+    #[error("Could not connect to the adapter: {0}")]
+    ProbeConnect(io::Error),
+    #[error("The probe request failed: {0}")]
+    ProbeRequest(io::Error),
+    #[error("Could not parse the probe response: {0}")]
+    ProbeResponse(String),
+    #[error("No notification parameters specified")]
+    NotifyEmpty,
+    #[error("The PID of the calling process is out of range")]
+    NotifyPid,
+    #[error("Could not send the notification: {0}")]
+    NotifySend(nix::errno::Errno),
+    #[error("Timed out waiting for the notification to be processed")]
+    NotifyBarrierTimeout,
     #[error("Could not split notify socket message")]
     MessageSplit(String),
     #[error("Could not parse value of socket message as integer: {0}")]
     MessageParseInt(ParseIntError),
-    #[error("Could not parse value of socket message as float: {0}")]
-    MessageParseFloat(ParseFloatError),
     #[error("The provided message is not a well-known assignment: {0}")]
     MessageUndefined(String),
+    #[error("Could not find the command of the service: {0}")]
+    SupervisorCommand(String),
+    #[error("The adapter did not become ready to start the service")]
+    SupervisorStart,
+    #[error("Could not start the service: {0}")]
+    SupervisorSpawn(io::Error),
+    #[error("Could not wait for the service to exit: {0}")]
+    SupervisorWait(nix::errno::Errno),
+    #[error("Could not send a signal to the service: {0}")]
+    SupervisorKill(nix::errno::Errno),
+    #[error("The service exited with status {0}")]
+    ServiceExit(i32),
+    #[error("The job has completed with status {0}")]
+    JobComplete(i32),
+    #[error("Could not parse Kubernetes API URL from: {0}")]
+    KubernetesUrl(String),
+    #[error("Could not read the Kubernetes CA file {}: {1}", .0.display())]
+    KubernetesCaFile(PathBuf, io::Error),
+    #[error("Could not use the Kubernetes CA certificates: {0}")]
+    KubernetesTls(rustls::Error),
+    #[error("Could not read the Kubernetes namespace of the Pod: {0}")]
+    KubernetesNamespace(io::Error),
+    #[error("ADAPTER_KUBERNETES_POD_NAME is not set")]
+    KubernetesPodName,
+    #[error("Could not read the Kubernetes token file {}: {1}", .0.display())]
+    KubernetesToken(PathBuf, io::Error),
+    #[error("The Kubernetes API request failed: {0}")]
+    KubernetesRequest(String),
+    #[error("The Kubernetes API responded with status {0}: {1}")]
+    KubernetesResponse(u16, String),
+    #[error("The service has run for longer than ADAPTER_UNIT_RUNTIME_MAX_SEC")]
+    RuntimeTimeout,
     #[error("Could not setup up listener for unix signal: {0}")]
     Signal(io::Error),
     #[error("Could not join the task: {0}")]
     Join(JoinError),
 }
+
+impl Error {
+    /// The exit code of the adapter when this error has shut it down, which for a supervised
+    /// service that has exited is its own
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::ServiceExit(code) | Self::EventShutdown(_, code) | Self::JobComplete(code) => {
+                *code
+            }
+            Self::Config(_)
+            | Self::ConfigFileRead(..)
+            | Self::ConfigFileParse(..)
+            | Self::ConfigFileKey(..)
+            | Self::ConfigFileValue(..)
+            | Self::UnitFileRead(..)
+            | Self::UnitFileValue(..)
+            | Self::ConfigOption(_)
+            | Self::LogFileOpen(..)
+            | Self::KubernetesUrl(_)
+            | Self::KubernetesCaFile(..)
+            | Self::KubernetesTls(_)
+            | Self::KubernetesNamespace(_)
+            | Self::KubernetesPodName => EXIT_CONFIG,
+            Self::UdsDeleteSocket(_)
+            | Self::UdsCreateSocket(_)
+            | Self::UdsGetSocketOption(_)
+            | Self::UdsSetSocketOption(_)
+            | Self::UdsReceiveDatagram(_)
+            | Self::UdsShutdown(_)
+            | Self::HttpBindAddress(_)
+            | Self::Http(_)
+            | Self::AdminBindAddress(_)
+            | Self::Admin(_)
+            | Self::GrpcBindAddress(_)
+            | Self::Grpc(_) => EXIT_SOCKET,
+            Self::ConfigChannelSend(_)
+            | Self::ConfigChannelClosed
+            | Self::StatusChannelSend(_)
+            | Self::StatusChannelClosed
+            | Self::WatchdogChannelSend(_)
+            | Self::WatchdogChannelClosed
+            | Self::EventChannelSend(_)
+            | Self::EventChannelClosed
+            | Self::ShutdownChannelClosed
+            | Self::ReadyChannelSend(_) => EXIT_CHANNEL,
+            Self::RuntimeTimeout => EXIT_RUNTIME,
+            _ => EXIT_FAILURE,
+        }
+    }
+
+    /// Whether a notification was rejected because it is malformed, rather than because the
+    /// adapter could not process it
+    pub fn is_message_error(&self) -> bool {
+        matches!(
+            self,
+            Self::MessageSplit(_)
+                | Self::MessageParseInt(_)
+                | Self::MessageUndefined(_)
+                | Self::ParseNotifyAccess(_)
+        )
+    }
+}
@@ -1,18 +1,21 @@
-use std::{str::FromStr, sync::Arc};
+use std::{fmt, str::FromStr, sync::Arc};
 
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::{
+    broadcast,
+    mpsc::{Receiver, Sender},
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{info, instrument};
 use valuable::Valuable;
 
 use crate::{
     config::SharedConfiguration,
-    error::Error,
-    status::{Change, ChangeOperation},
+    error::{Error, EXIT_EVENT, EXIT_FAILURE},
+    status::{Change, ChangeOperation, Condition, Reason, Update},
     timer::watchdog::Message,
 };
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Valuable)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Event {
     Ready,
     Reloading,
@@ -23,6 +26,9 @@ pub enum Event {
     WatchdogTrigger,
     WatchdogTimeout,
     StartTimeout,
+    /// The supervised service has exited with a status, which is 128 plus the signal number if
+    /// a signal terminated it
+    Exit(i32),
 }
 
 impl FromStr for Event {
@@ -39,13 +45,63 @@ impl FromStr for Event {
             "watchdog_trigger" => Ok(Self::WatchdogTrigger),
             "watchdog_timeout" => Ok(Self::WatchdogTimeout),
             "start_timeout" => Ok(Self::StartTimeout),
+            // Lists only name the kind of event, so the status is a placeholder
+            "exit" => Ok(Self::Exit(0)),
             _ => Err(Error::ParseEvent(s.into())),
         }
     }
 }
 
+impl Event {
+    /// The probe condition that this event reports on
+    fn condition(self) -> Condition {
+        match self {
+            Self::Ready | Self::StartTimeout => Condition::Startup,
+            Self::Reloading | Self::Stopping => Condition::Stopping,
+            Self::ErrorNumber => Condition::ErrorNumber,
+            Self::BusError => Condition::BusError,
+            Self::Watchdog | Self::WatchdogTrigger | Self::WatchdogTimeout => Condition::Watchdog,
+            Self::Exit(_) => Condition::Exit,
+        }
+    }
+}
+
+impl Event {
+    /// The name of the event in the configuration
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ready => "ready",
+            Self::Reloading => "reloading",
+            Self::Stopping => "stopping",
+            Self::ErrorNumber => "errno",
+            Self::BusError => "buserror",
+            Self::Watchdog => "watchdog",
+            Self::WatchdogTrigger => "watchdog_trigger",
+            Self::WatchdogTimeout => "watchdog_timeout",
+            Self::StartTimeout => "start_timeout",
+            Self::Exit(_) => "exit",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Valuable for Event {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(self.name())
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
-#[derive(Clone, Valuable)]
+#[derive(Clone, Valuable, PartialEq)]
 pub struct EventList(Arc<[Event]>);
 
 impl FromStr for EventList {
@@ -62,8 +118,79 @@ impl FromStr for EventList {
 }
 
 impl EventList {
-    fn contains(&self, event: &Event) -> bool {
-        self.0.contains(event)
+    pub fn contains(&self, event: Event) -> bool {
+        self.0.iter().any(|listed| listed.name() == event.name())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// The exit codes of the adapter after events in `ADAPTER_STATUS_SHUTDOWN`, e.g.
+/// `watchdog_timeout=10,start_timeout=11` or `stopping=success,watchdog_timeout=failure`
+#[derive(Clone)]
+pub struct EventCodes {
+    codes: Arc<[(Event, i32)]>,
+    source: Arc<str>,
+}
+
+impl EventCodes {
+    /// The exit code for an event, which is the status of the service for `exit` unless it is
+    /// listed
+    pub fn code(&self, event: Event) -> i32 {
+        let listed = self
+            .codes
+            .iter()
+            .find(|(listed, _)| listed.name() == event.name());
+        match (listed, event) {
+            (Some((_, code)), _) => *code,
+            (None, Event::Exit(status)) => status,
+            (None, _) => EXIT_EVENT,
+        }
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = Event> + '_ {
+        self.codes.iter().map(|(event, _)| *event)
+    }
+}
+
+impl FromStr for EventCodes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let codes: Result<Arc<[_]>, _> = s
+            .split(',')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let error = || Error::ParseEventCodes(entry.into());
+                let (event, code) = entry.split_once('=').ok_or_else(error)?;
+                let code = match code {
+                    "success" => 0,
+                    "failure" => EXIT_FAILURE,
+                    code => i32::from(code.parse::<u8>().map_err(|_| error())?),
+                };
+                Ok((event.parse()?, code))
+            })
+            .collect();
+        Ok(EventCodes {
+            codes: codes?,
+            source: s.into(),
+        })
+    }
+}
+
+impl Valuable for EventCodes {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(&self.source)
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
     }
 }
 
@@ -75,15 +202,8 @@ pub async fn event_listener(
     ready_sender: Sender<()>,
     watchdog_sender: Sender<Message>,
     status_sender: Sender<Change>,
+    update_sender: broadcast::Sender<Update>,
 ) -> Result<(), Error> {
-    // Event lists should not change during runtime
-    let config_lock = config.read().await;
-    let status_livez_true = config_lock.status_livez_true.clone();
-    let status_livez_false = config_lock.status_livez_false.clone();
-    let status_readyz_true = config_lock.status_readyz_true.clone();
-    let status_readyz_false = config_lock.status_readyz_false.clone();
-    let status_shutdown = config_lock.status_shutdown.clone();
-
     info!("Event listener ready");
     ready_sender
         .send(())
@@ -114,27 +234,39 @@ pub async fn event_listener(
             _ => {}
         }
 
+        // Event lists can change when the configuration is reloaded
+        let config_lock = config.read().await;
         let healthz_operation = ChangeOperation::Keep;
         let mut livez_operation = ChangeOperation::Keep;
         let mut readyz_operation = ChangeOperation::Keep;
 
-        if status_shutdown.contains(&event) {
-            return Err(Error::EventShutdown(event));
+        if config_lock.status_shutdown.contains(event) {
+            let code = config_lock.status_shutdown_codes.code(event);
+            return Err(Error::EventShutdown(event, code));
         }
 
-        if status_livez_true.contains(&event) {
-            livez_operation = ChangeOperation::Set(true);
+        let reason = Reason {
+            condition: event.condition(),
+            message: match event {
+                Event::Exit(status) => format!("The service exited with status {status}"),
+                _ => format!("Received event {event}"),
+            },
+        };
+        if config_lock.status_livez_true.contains(event) {
+            livez_operation = ChangeOperation::Set(true, reason.clone());
         }
-        if status_livez_false.contains(&event) {
-            livez_operation = ChangeOperation::Set(false);
+        if config_lock.status_livez_false.contains(event) {
+            livez_operation = ChangeOperation::Set(false, reason.clone());
         }
-        if status_readyz_true.contains(&event) {
-            readyz_operation = ChangeOperation::Set(true);
+        if config_lock.status_readyz_true.contains(event) {
+            readyz_operation = ChangeOperation::Set(true, reason.clone());
         }
-        if status_readyz_false.contains(&event) {
-            readyz_operation = ChangeOperation::Set(false);
+        if config_lock.status_readyz_false.contains(event) {
+            readyz_operation = ChangeOperation::Set(false, reason);
         }
 
+        drop(config_lock);
+
         status_sender
             .send(Change {
                 healthz: healthz_operation,
@@ -143,9 +275,75 @@ pub async fn event_listener(
             })
             .await
             .map_err(Error::StatusChannelSend)?;
+
+        // Having no subscribers is not an error
+        let _ = update_sender.send(Update::Event(event));
     }
 
     info!("Shutting down event listener");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_codes_parse() {
+        let codes: EventCodes = "watchdog_timeout=10,stopping=success,start_timeout=failure"
+            .parse()
+            .expect("valid event codes");
+        assert_eq!(codes.code(Event::WatchdogTimeout), 10);
+        assert_eq!(codes.code(Event::Stopping), 0);
+        assert_eq!(codes.code(Event::StartTimeout), EXIT_FAILURE);
+        assert_eq!(
+            codes.events().collect::<Vec<_>>(),
+            [Event::WatchdogTimeout, Event::Stopping, Event::StartTimeout]
+        );
+        assert_eq!(
+            "".parse::<EventCodes>()
+                .expect("empty event codes")
+                .events()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn event_codes_default() {
+        let codes: EventCodes = "errno=20".parse().expect("valid event codes");
+        assert_eq!(codes.code(Event::WatchdogTrigger), EXIT_EVENT);
+        assert_eq!(codes.code(Event::Exit(7)), 7);
+        let codes: EventCodes = "exit=42".parse().expect("valid event codes");
+        assert_eq!(codes.code(Event::Exit(7)), 42);
+    }
+
+    #[test]
+    fn event_codes_reject_invalid_input() {
+        for invalid in [
+            "errno",
+            "errno=",
+            "errno=256",
+            "errno=-1",
+            "errno=ok",
+            "unknown=1",
+            "errno=1,,stopping",
+        ] {
+            assert!(invalid.parse::<EventCodes>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn event_list_parse() {
+        let events: EventList = "ready,exit".parse().expect("valid event list");
+        assert!(events.contains(Event::Ready));
+        assert!(events.contains(Event::Exit(1)));
+        assert!(!events.contains(Event::Stopping));
+        assert!(""
+            .parse::<EventList>()
+            .expect("empty event list")
+            .is_empty());
+        assert!("ready,".parse::<EventList>().is_err());
+    }
+}
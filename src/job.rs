@@ -0,0 +1,85 @@
+use tokio::{
+    sync::{broadcast, mpsc::Sender},
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument};
+
+use crate::{
+    config::{Seconds, SharedConfiguration},
+    error::{Error, EXIT_FAILURE},
+    event::Event,
+    service::SharedService,
+    shutdown::EXIT_INTERVAL,
+    status::Update,
+};
+
+/// Shuts down the adapter once the service has run to completion, like the main container of a
+/// Kubernetes `Job`, with an exit code derived from what the service has reported
+#[instrument(name = "Job watcher", skip_all)]
+pub async fn watcher(
+    token: CancellationToken,
+    config: SharedConfiguration,
+    service: SharedService,
+    ready_sender: Sender<()>,
+    update_sender: broadcast::Sender<Update>,
+) -> Result<(), Error> {
+    let mut updates = update_sender.subscribe();
+    let started = Instant::now();
+    let mut stopping = None;
+
+    info!("Job watcher ready");
+    ready_sender
+        .send(())
+        .await
+        .map_err(Error::ReadyChannelSend)?;
+
+    loop {
+        tokio::select! {
+            () = token.cancelled() => break,
+            update = updates.recv() => {
+                if let Ok(Update::Event(Event::Stopping)) = update {
+                    stopping.get_or_insert_with(Instant::now);
+                }
+            },
+            () = time::sleep(EXIT_INTERVAL) => {},
+        }
+
+        let config_lock = config.read().await;
+        let silence_sec = config_lock.job_silence_sec;
+        let stop_timeout = config_lock.unit_timeout_stop_sec;
+        drop(config_lock);
+
+        let service_lock = service.read().await;
+        let silent_since = service_lock.last_message.unwrap_or(started);
+        // A main process from another PID namespace leaves only `STOPPING=1` and `EXIT_STATUS=`
+        let exited = service_lock.main_process_alive() == Some(false);
+        let reason = match stopping {
+            Some(_) if service_lock.exit_status.is_some() => {
+                String::from("The service has sent STOPPING=1 and EXIT_STATUS=")
+            }
+            _ if exited => String::from("The main process has exited"),
+            Some(since) if since.elapsed() >= stop_timeout.into() => String::from(
+                "The service has not sent EXIT_STATUS= within ADAPTER_UNIT_TIMEOUT_STOP_SEC",
+            ),
+            _ if silence_sec != Seconds::INFINITY
+                && silent_since.elapsed() >= silence_sec.into() =>
+            {
+                format!("The service has not sent a message for {silence_sec}")
+            }
+            _ => continue,
+        };
+
+        // Without `EXIT_STATUS=`, only a service that has sent `STOPPING=1` has succeeded
+        let code = match (service_lock.exit_status, stopping) {
+            (Some(status), _) => status,
+            (None, Some(_)) => 0,
+            (None, None) => EXIT_FAILURE,
+        };
+        info!(code, "Job completed: {}", reason);
+        return Err(Error::JobComplete(code));
+    }
+
+    info!("Shutting down job watcher");
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use std::{error::Error as _, io, path::PathBuf, sync::Arc, time::Duration};
+
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::Bytes,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    Method, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client as HttpClient},
+    rt::TokioExecutor,
+};
+use rustls::{crypto::ring, ClientConfig, RootCertStore};
+use tokio::{fs, time};
+
+use crate::{config::Configuration, error::Error};
+
+/// How long a request to the Kubernetes API may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the namespace of the service account is mounted, next to its token
+const NAMESPACE_FILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Talks to the Kubernetes API about the `Pod` of the adapter, with the token of its service
+/// account
+pub struct Client {
+    http: HttpClient<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    url: Arc<str>,
+    token_file: PathBuf,
+    pub namespace: String,
+    pub pod_name: String,
+}
+
+impl Client {
+    /// Sets up the client, where an `http://` URL, e.g. of a mock server, needs no CA file
+    pub async fn new(config: &Configuration) -> Result<Self, Error> {
+        let url = config.kubernetes_api_url.0.trim_end_matches('/');
+        let mut roots = RootCertStore::empty();
+        if url.starts_with("https://") {
+            let ca_file = PathBuf::from(&*config.kubernetes_ca_file.0);
+            let pem = fs::read(&ca_file)
+                .await
+                .map_err(|error| Error::KubernetesCaFile(ca_file.clone(), error))?;
+            for certificate in rustls_pemfile::certs(&mut pem.as_slice()) {
+                let certificate =
+                    certificate.map_err(|error| Error::KubernetesCaFile(ca_file.clone(), error))?;
+                roots.add(certificate).map_err(Error::KubernetesTls)?;
+            }
+        } else if !url.starts_with("http://") {
+            return Err(Error::KubernetesUrl(url.into()));
+        }
+        let tls = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(Error::KubernetesTls)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        let namespace = match &config.kubernetes_pod_namespace {
+            Some(namespace) => namespace.0.to_string(),
+            None => fs::read_to_string(NAMESPACE_FILE)
+                .await
+                .map_err(Error::KubernetesNamespace)?
+                .trim()
+                .into(),
+        };
+        let pod_name = config
+            .kubernetes_pod_name
+            .as_ref()
+            .ok_or(Error::KubernetesPodName)?
+            .0
+            .to_string();
+
+        Ok(Client {
+            http: HttpClient::builder(TokioExecutor::new()).build(connector),
+            url: url.into(),
+            token_file: PathBuf::from(&*config.kubernetes_token_file.0),
+            namespace,
+            pod_name,
+        })
+    }
+
+    /// Sends a JSON body to a path of the API, where any status other than a success fails
+    pub async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        content_type: &str,
+        body: &serde_json::Value,
+    ) -> Result<(), Error> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("{}{path}", self.url))
+            .header(CONTENT_TYPE, content_type)
+            .header(ACCEPT, "application/json");
+        // The token is read for every request, since the kubelet rotates it, and a mock server
+        // may not need one
+        match fs::read_to_string(&self.token_file).await {
+            Ok(token) => {
+                request = request.header(AUTHORIZATION, format!("Bearer {}", token.trim()));
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(Error::KubernetesToken(self.token_file.clone(), error)),
+        }
+        let request = request
+            .body(Full::new(Bytes::from(body.to_string())))
+            .map_err(|error| Error::KubernetesRequest(error.to_string()))?;
+
+        let response = time::timeout(REQUEST_TIMEOUT, self.http.request(request))
+            .await
+            .map_err(|_| Error::KubernetesRequest(String::from("Timed out")))?
+            .map_err(|error| {
+                // The error of the client itself only names the stage that has failed
+                let mut message = error.to_string();
+                let mut source = error.source();
+                while let Some(error) = source {
+                    message = format!("{message}: {error}");
+                    source = error.source();
+                }
+                Error::KubernetesRequest(message)
+            })?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response
+                .into_body()
+                .collect()
+                .await
+                .map(|body| String::from_utf8_lossy(&body.to_bytes()).into_owned())
+                .unwrap_or_default();
+            return Err(Error::KubernetesResponse(status.as_u16(), body));
+        }
+        Ok(())
+    }
+
+    /// The path of the `Pod` in the API
+    pub fn pod_path(&self) -> String {
+        format!(
+            "/api/v1/namespaces/{}/pods/{}",
+            self.namespace, self.pod_name
+        )
+    }
+}
+
+/// A mock of the Kubernetes API, which records the requests it receives
+#[cfg(test)]
+pub mod mock {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use axum::{extract::State, http::HeaderMap, Router};
+    use hyper::{header::CONTENT_TYPE, Method, StatusCode, Uri};
+    use tokio::{net::TcpListener, time};
+
+    use crate::config::{ConfigString, Configuration};
+
+    #[derive(Clone)]
+    pub struct Request {
+        pub method: Method,
+        pub path: String,
+        pub content_type: String,
+        pub body: serde_json::Value,
+    }
+
+    #[derive(Clone, Default)]
+    pub struct Mock {
+        requests: Arc<Mutex<Vec<Request>>>,
+        /// The statuses of the next responses, after which every request succeeds
+        statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+    }
+
+    impl Mock {
+        /// Serves the API on a free port, and returns a configuration that uses it
+        pub async fn start(statuses: &[u16]) -> (Self, Configuration) {
+            let mock = Mock::default();
+            mock.statuses.lock().expect("statuses").extend(
+                statuses
+                    .iter()
+                    .map(|status| StatusCode::from_u16(*status).expect("status")),
+            );
+            let listener = TcpListener::bind("127.0.0.1:0").await.expect("listener");
+            let address = listener.local_addr().expect("address");
+            let app = Router::new().fallback(record).with_state(mock.clone());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+
+            let mut config = Configuration::defaults().expect("default configuration");
+            config.kubernetes_api_url = ConfigString(format!("http://{address}").into());
+            config.kubernetes_token_file = ConfigString("/nonexistent/token".into());
+            config.kubernetes_pod_name = Some(ConfigString("web-0".into()));
+            config.kubernetes_pod_namespace = Some(ConfigString("default".into()));
+            (mock, config)
+        }
+
+        pub fn requests(&self) -> Vec<Request> {
+            self.requests.lock().expect("requests").clone()
+        }
+
+        /// Waits until the API has received a number of requests
+        pub async fn wait(&self, count: usize) -> Vec<Request> {
+            time::timeout(Duration::from_secs(10), async {
+                loop {
+                    let requests = self.requests();
+                    if requests.len() >= count {
+                        return requests;
+                    }
+                    time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("requests")
+        }
+    }
+
+    async fn record(
+        State(mock): State<Mock>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        mock.requests.lock().expect("requests").push(Request {
+            method,
+            path: uri.path().into(),
+            content_type: headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .into(),
+            body: serde_json::from_str(&body).expect("JSON body"),
+        });
+        mock.statuses
+            .lock()
+            .expect("statuses")
+            .pop_front()
+            .unwrap_or(StatusCode::OK)
+    }
+}
@@ -0,0 +1,316 @@
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+use hyper::Method;
+use serde_json::json;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::Sender,
+    },
+    time,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    config::SharedConfiguration,
+    error::Error,
+    kubernetes::client::Client,
+    service::SharedService,
+    status::{SharedStatus, Update},
+};
+
+/// How long to wait before the first retry of a failed update, which doubles with every retry
+const RETRY_MIN: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// Keeps a custom condition of the `Pod` in line with `/readyz` and `STATUS=`, e.g. for
+/// `readinessGates`, without holding up the status writer, since it only follows the updates
+#[instrument(name = "Pod condition", skip_all)]
+pub async fn patcher(
+    token: CancellationToken,
+    config: SharedConfiguration,
+    status: SharedStatus,
+    service: SharedService,
+    ready_sender: Sender<()>,
+    update_sender: broadcast::Sender<Update>,
+) -> Result<(), Error> {
+    let mut updates = update_sender.subscribe();
+    let client = Client::new(&*config.read().await).await?;
+
+    info!("Pod condition ready");
+    ready_sender
+        .send(())
+        .await
+        .map_err(Error::ReadyChannelSend)?;
+
+    // Sent once when the adapter starts, so that the condition exists before its first change
+    let mut pending = true;
+    let mut retry = None;
+    let mut patched = None;
+    loop {
+        if !pending {
+            tokio::select! {
+                () = token.cancelled() => break,
+                update = updates.recv() => match update {
+                    Ok(Update::Status(_) | Update::Text(_)) | Err(RecvError::Lagged(_)) => {
+                        pending = true;
+                    }
+                    Ok(Update::Event(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+            }
+            continue;
+        }
+
+        // Changes in the meantime are sent together, since only the latest state is sent
+        let delay = retry.unwrap_or(config.read().await.kubernetes_batch_sec.into());
+        let result = tokio::select! {
+            () = token.cancelled() => break,
+            result = async {
+                time::sleep(delay).await;
+                patch(&client, &config, &status, &service, &mut patched).await
+            } => result,
+        };
+        match result {
+            Ok(()) => {
+                pending = false;
+                retry = None;
+            }
+            Err(error) => {
+                let next = retry.map_or(RETRY_MIN, |retry: Duration| (retry * 2).min(RETRY_MAX));
+                warn!(
+                    "Could not update the Pod condition, retrying in {:?}: {}",
+                    next, error
+                );
+                retry = Some(next);
+            }
+        }
+    }
+
+    info!("Shutting down Pod condition");
+    Ok(())
+}
+
+/// The condition as it was last sent
+#[derive(PartialEq)]
+struct Patched {
+    value: bool,
+    reason: String,
+    message: String,
+    transition_time: String,
+}
+
+/// Sends the current state as the condition, unless it has not changed since it was last sent,
+/// e.g. when only another probe has changed
+async fn patch(
+    client: &Client,
+    config: &SharedConfiguration,
+    status: &SharedStatus,
+    service: &SharedService,
+    patched: &mut Option<Patched>,
+) -> Result<(), Error> {
+    let Some(condition) = config.read().await.kubernetes_condition.clone() else {
+        return Ok(());
+    };
+    let readyz = status.read().await.readyz.clone();
+    let text = service.read().await.status.clone();
+
+    let value = readyz.value();
+    let failing = readyz.checks().find(|(_, check)| !check.passing);
+    let reason = match failing.filter(|_| !value) {
+        Some((condition, _)) => {
+            // Reasons are written in `CamelCase`, and conditions are single lowercase words
+            let name = condition.to_string();
+            let mut chars = name.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        None => String::from("Passing"),
+    };
+    let message = text
+        .or_else(|| failing.map(|(_, check)| check.reason.clone()))
+        .unwrap_or_default();
+
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let transition_time = match patched {
+        Some(patched) if patched.value == value => patched.transition_time.clone(),
+        _ => now.clone(),
+    };
+    let current = Patched {
+        value,
+        reason,
+        message,
+        transition_time,
+    };
+    if patched.as_ref() == Some(&current) {
+        return Ok(());
+    }
+    let body = json!({
+        "status": {
+            "conditions": [{
+                "type": &*condition.0,
+                "status": if value { "True" } else { "False" },
+                "reason": current.reason,
+                "message": current.message,
+                "lastProbeTime": now,
+                "lastTransitionTime": current.transition_time,
+            }],
+        },
+    });
+    client
+        .send(
+            Method::PATCH,
+            &format!("{}/status", client.pod_path()),
+            "application/strategic-merge-patch+json",
+            &body,
+        )
+        .await?;
+    info!(value, reason = current.reason, "Updated the Pod condition");
+    *patched = Some(current);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::{mpsc, RwLock};
+
+    use super::*;
+    use crate::{
+        config::ConfigString,
+        kubernetes::client::mock::Mock,
+        status::{ChangeOperation, Condition, Reason, Status},
+    };
+
+    async fn setup(statuses: &[u16]) -> (Mock, SharedConfiguration, SharedStatus, SharedService) {
+        let (mock, mut config) = Mock::start(statuses).await;
+        config.kubernetes_condition = Some(ConfigString("example.com/Ready".into()));
+        config.kubernetes_batch_sec = "200ms".parse().expect("batch");
+        let status = Arc::new(RwLock::new(Status::from_config(&config)));
+        let config = Arc::new(RwLock::new(config));
+        (mock, config, status, SharedService::default())
+    }
+
+    fn ready(status: &mut Status) {
+        status.readyz.apply(ChangeOperation::Set(
+            true,
+            Reason {
+                condition: Condition::Initial,
+                message: String::from("Ready"),
+            },
+        ));
+    }
+
+    #[tokio::test]
+    async fn patch_sends_the_condition_when_it_changes() {
+        let (mock, config, status, service) = setup(&[]).await;
+        let client = Client::new(&*config.read().await).await.expect("client");
+        let mut patched = None;
+
+        patch(&client, &config, &status, &service, &mut patched)
+            .await
+            .expect("patch");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, Method::PATCH);
+        assert_eq!(
+            requests[0].path,
+            "/api/v1/namespaces/default/pods/web-0/status"
+        );
+        assert_eq!(
+            requests[0].content_type,
+            "application/strategic-merge-patch+json"
+        );
+        let condition = &requests[0].body["status"]["conditions"][0];
+        assert_eq!(condition["type"], "example.com/Ready");
+        assert_eq!(condition["status"], "False");
+        assert_eq!(condition["reason"], "Initial");
+        assert_eq!(condition["message"], "ADAPTER_INITIAL_READYZ is false");
+
+        // Nothing has changed
+        patch(&client, &config, &status, &service, &mut patched)
+            .await
+            .expect("patch");
+        assert_eq!(mock.requests().len(), 1);
+
+        ready(&mut *status.write().await);
+        patch(&client, &config, &status, &service, &mut patched)
+            .await
+            .expect("patch");
+        service.write().await.status = Some(String::from("Serving"));
+        patch(&client, &config, &status, &service, &mut patched)
+            .await
+            .expect("patch");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        let passing = &requests[1].body["status"]["conditions"][0];
+        assert_eq!(passing["status"], "True");
+        assert_eq!(passing["reason"], "Passing");
+        let text = &requests[2].body["status"]["conditions"][0];
+        assert_eq!(text["status"], "True");
+        assert_eq!(text["message"], "Serving");
+        assert_eq!(text["lastTransitionTime"], passing["lastTransitionTime"]);
+    }
+
+    #[tokio::test]
+    async fn patch_is_sent_again_after_a_failure() {
+        let (mock, config, status, service) = setup(&[503]).await;
+        let client = Client::new(&*config.read().await).await.expect("client");
+        let mut patched = None;
+
+        let result = patch(&client, &config, &status, &service, &mut patched).await;
+        assert!(matches!(result, Err(Error::KubernetesResponse(503, _))));
+        patch(&client, &config, &status, &service, &mut patched)
+            .await
+            .expect("patch");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].body["status"]["conditions"][0]["status"],
+            requests[1].body["status"]["conditions"][0]["status"]
+        );
+    }
+
+    #[tokio::test]
+    async fn patcher_retries_and_batches_changes() {
+        let (mock, config, status, service) = setup(&[503]).await;
+        let token = CancellationToken::new();
+        let (ready_sender, mut ready_receiver) = mpsc::channel(1);
+        let (update_sender, _) = broadcast::channel(16);
+        let patcher = tokio::spawn(patcher(
+            token.clone(),
+            config,
+            status.clone(),
+            service.clone(),
+            ready_sender,
+            update_sender.clone(),
+        ));
+        ready_receiver.recv().await.expect("ready");
+
+        // The first patch fails and is retried after `RETRY_MIN`
+        let requests = mock.wait(2).await;
+        assert_eq!(requests.len(), 2);
+
+        // Changes within `ADAPTER_KUBERNETES_BATCH_SEC` are sent together
+        ready(&mut *status.write().await);
+        let update = Update::Status(status.read().await.clone());
+        assert!(update_sender.send(update).is_ok());
+        service.write().await.status = Some(String::from("Serving"));
+        let update = Update::Text(String::from("Serving"));
+        assert!(update_sender.send(update).is_ok());
+        let requests = mock.wait(3).await;
+        let condition = &requests[2].body["status"]["conditions"][0];
+        assert_eq!(condition["status"], "True");
+        assert_eq!(condition["message"], "Serving");
+        time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(mock.requests().len(), 3);
+
+        token.cancel();
+        patcher.await.expect("join").expect("patcher");
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use hyper::Method;
+use serde_json::json;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::Sender,
+    },
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    config::SharedConfiguration, error::Error, event::Event, kubernetes::client::Client,
+    service::SharedService, status::Update,
+};
+
+/// The occurrences of a kind of event, which are aggregated into a single Kubernetes `Event`
+struct Aggregate {
+    event: Event,
+    /// The name of the `Event` once it has been created
+    name: Option<String>,
+    count: u32,
+    /// The count that the API has last been sent
+    sent: u32,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+    message: String,
+    /// When the next request for this kind of event may be sent
+    next: Instant,
+}
+
+/// Records the configured events of the service as Kubernetes `Event`s of its `Pod`, where
+/// each kind of event is aggregated into one `Event` with a count that is sent at most once per
+/// `ADAPTER_KUBERNETES_EVENT_INTERVAL_SEC`
+#[instrument(name = "Kubernetes events", skip_all)]
+pub async fn recorder(
+    token: CancellationToken,
+    config: SharedConfiguration,
+    service: SharedService,
+    ready_sender: Sender<()>,
+    update_sender: broadcast::Sender<Update>,
+) -> Result<(), Error> {
+    let mut updates = update_sender.subscribe();
+    let client = Client::new(&*config.read().await).await?;
+    let uid = config
+        .read()
+        .await
+        .kubernetes_pod_uid
+        .as_ref()
+        .map(|uid| uid.0.to_string());
+
+    info!("Kubernetes events ready");
+    ready_sender
+        .send(())
+        .await
+        .map_err(Error::ReadyChannelSend)?;
+
+    let mut aggregates: BTreeMap<&'static str, Aggregate> = BTreeMap::new();
+    loop {
+        let due = aggregates
+            .values()
+            .filter(|aggregate| aggregate.count > aggregate.sent)
+            .map(|aggregate| aggregate.next)
+            .min();
+        tokio::select! {
+            () = token.cancelled() => break,
+            update = updates.recv() => match update {
+                Ok(Update::Event(event)) => {
+                    if !config.read().await.kubernetes_events.contains(event) {
+                        continue;
+                    }
+                    let message = message(event, &service).await;
+                    let now = Utc::now();
+                    let aggregate = aggregates.entry(event.name()).or_insert_with(|| Aggregate {
+                        event,
+                        name: None,
+                        count: 0,
+                        sent: 0,
+                        first: now,
+                        last: now,
+                        message: String::new(),
+                        next: Instant::now(),
+                    });
+                    aggregate.event = event;
+                    aggregate.count += 1;
+                    aggregate.last = now;
+                    aggregate.message = message;
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Missed {} updates for Kubernetes events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            () = time::sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                let interval = config.read().await.kubernetes_event_interval_sec;
+                let now = Instant::now();
+                for aggregate in aggregates.values_mut() {
+                    if aggregate.count == aggregate.sent || aggregate.next > now {
+                        continue;
+                    }
+                    // Failed requests count against the rate limit as well
+                    aggregate.next = now + interval.into();
+                    let result = tokio::select! {
+                        () = token.cancelled() => break,
+                        result = send(&client, uid.as_deref(), aggregate) => result,
+                    };
+                    match result {
+                        Ok(()) => {
+                            info!(
+                                event = aggregate.event.name(),
+                                count = aggregate.count,
+                                "Recorded Kubernetes event"
+                            );
+                            aggregate.sent = aggregate.count;
+                        }
+                        Err(error) => warn!(
+                            "Could not record the {} event, retrying in {}: {}",
+                            aggregate.event, interval, error
+                        ),
+                    }
+                }
+            },
+        }
+    }
+
+    info!("Shutting down Kubernetes events");
+    Ok(())
+}
+
+/// The latest `STATUS=` text of the service, or else a description of the event
+async fn message(event: Event, service: &SharedService) -> String {
+    let service = service.read().await;
+    if let Some(text) = &service.status {
+        return text.clone();
+    }
+    match event {
+        Event::ErrorNumber => match service.error_number {
+            Some(number) => format!("The service has sent ERRNO={number}"),
+            None => String::from("The service has sent ERRNO="),
+        },
+        Event::BusError => match &service.bus_error {
+            Some(error) => format!("The service has sent BUSERROR={error}"),
+            None => String::from("The service has sent BUSERROR="),
+        },
+        Event::WatchdogTimeout => String::from("The watchdog has timed out"),
+        Event::WatchdogTrigger => String::from("The service has sent WATCHDOG=trigger"),
+        Event::StartTimeout => String::from("The service has not sent READY=1 in time"),
+        Event::Exit(status) => format!("The service exited with status {status}"),
+        _ => format!("Received event {event}"),
+    }
+}
+
+/// Creates the `Event` of an aggregate, or updates its count if it exists
+async fn send(client: &Client, uid: Option<&str>, aggregate: &mut Aggregate) -> Result<(), Error> {
+    let timestamp = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let events = format!("/api/v1/namespaces/{}/events", client.namespace);
+
+    if let Some(name) = &aggregate.name {
+        let body = json!({
+            "count": aggregate.count,
+            "lastTimestamp": timestamp(aggregate.last),
+            "message": aggregate.message,
+        });
+        match client
+            .send(
+                Method::PATCH,
+                &format!("{events}/{name}"),
+                "application/merge-patch+json",
+                &body,
+            )
+            .await
+        {
+            // The API deletes `Event`s after a while, so it is created again
+            Err(Error::KubernetesResponse(404, _)) => aggregate.name = None,
+            result => return result,
+        }
+    }
+
+    let name = format!(
+        "{}.{:x}",
+        client.pod_name,
+        Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    );
+    let body = json!({
+        "apiVersion": "v1",
+        "kind": "Event",
+        "metadata": {
+            "name": name,
+            "namespace": client.namespace,
+        },
+        "involvedObject": {
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "name": client.pod_name,
+            "namespace": client.namespace,
+            "uid": uid,
+        },
+        "reason": reason(aggregate.event),
+        "message": aggregate.message,
+        "type": if warning(aggregate.event) { "Warning" } else { "Normal" },
+        "count": aggregate.count,
+        "firstTimestamp": timestamp(aggregate.first),
+        "lastTimestamp": timestamp(aggregate.last),
+        "source": {
+            "component": env!("CARGO_PKG_NAME"),
+        },
+        "reportingComponent": env!("CARGO_PKG_NAME"),
+        "reportingInstance": client.pod_name,
+    });
+    client
+        .send(Method::POST, &events, "application/json", &body)
+        .await?;
+    aggregate.name = Some(name);
+    Ok(())
+}
+
+/// The name of an event in `CamelCase`, like the reasons of Kubernetes `Event`s
+fn reason(event: Event) -> &'static str {
+    match event {
+        Event::Ready => "Ready",
+        Event::Reloading => "Reloading",
+        Event::Stopping => "Stopping",
+        Event::ErrorNumber => "ErrorNumber",
+        Event::BusError => "BusError",
+        Event::Watchdog => "Watchdog",
+        Event::WatchdogTrigger => "WatchdogTrigger",
+        Event::WatchdogTimeout => "WatchdogTimeout",
+        Event::StartTimeout => "StartTimeout",
+        Event::Exit(_) => "Exit",
+    }
+}
+
+fn warning(event: Event) -> bool {
+    match event {
+        Event::ErrorNumber
+        | Event::BusError
+        | Event::WatchdogTrigger
+        | Event::WatchdogTimeout
+        | Event::StartTimeout => true,
+        Event::Exit(status) => status != 0,
+        Event::Ready | Event::Reloading | Event::Stopping | Event::Watchdog => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::sync::{mpsc, RwLock};
+
+    use super::*;
+    use crate::kubernetes::client::mock::Mock;
+
+    fn aggregate(count: u32) -> Aggregate {
+        let now = Utc::now();
+        Aggregate {
+            event: Event::WatchdogTrigger,
+            name: None,
+            count,
+            sent: 0,
+            first: now,
+            last: now,
+            message: String::from("The service has sent WATCHDOG=trigger"),
+            next: Instant::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_creates_and_updates_the_event() {
+        let (mock, config) = Mock::start(&[]).await;
+        let client = Client::new(&config).await.expect("client");
+        let mut aggregate = aggregate(1);
+
+        send(&client, Some("0b7d0c4e"), &mut aggregate)
+            .await
+            .expect("send");
+        let name = aggregate.name.clone().expect("name");
+        aggregate.count = 3;
+        send(&client, Some("0b7d0c4e"), &mut aggregate)
+            .await
+            .expect("send");
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(requests[0].path, "/api/v1/namespaces/default/events");
+        assert_eq!(requests[0].body["metadata"]["name"], name);
+        assert_eq!(requests[0].body["involvedObject"]["name"], "web-0");
+        assert_eq!(requests[0].body["involvedObject"]["uid"], "0b7d0c4e");
+        assert_eq!(requests[0].body["reason"], "WatchdogTrigger");
+        assert_eq!(requests[0].body["type"], "Warning");
+        assert_eq!(requests[0].body["count"], 1);
+        assert_eq!(requests[1].method, Method::PATCH);
+        assert_eq!(
+            requests[1].path,
+            format!("/api/v1/namespaces/default/events/{name}")
+        );
+        assert_eq!(requests[1].content_type, "application/merge-patch+json");
+        assert_eq!(requests[1].body["count"], 3);
+    }
+
+    #[tokio::test]
+    async fn send_creates_a_deleted_event_again() {
+        let (mock, config) = Mock::start(&[404]).await;
+        let client = Client::new(&config).await.expect("client");
+        let mut aggregate = aggregate(2);
+        aggregate.name = Some(String::from("web-0.deleted"));
+
+        send(&client, None, &mut aggregate).await.expect("send");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, Method::PATCH);
+        assert_eq!(requests[1].method, Method::POST);
+        assert_eq!(requests[1].body["count"], 2);
+        assert_ne!(aggregate.name.as_deref(), Some("web-0.deleted"));
+    }
+
+    #[tokio::test]
+    async fn send_keeps_a_failed_event_uncreated() {
+        let (mock, config) = Mock::start(&[503]).await;
+        let client = Client::new(&config).await.expect("client");
+        let mut aggregate = aggregate(1);
+
+        let result = send(&client, None, &mut aggregate).await;
+        assert!(matches!(result, Err(Error::KubernetesResponse(503, _))));
+        assert!(aggregate.name.is_none());
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn recorder_aggregates_and_retries_events() {
+        let (mock, mut config) = Mock::start(&[503]).await;
+        config.kubernetes_events = "watchdog_trigger".parse().expect("events");
+        config.kubernetes_event_interval_sec = "300ms".parse().expect("interval");
+        let token = CancellationToken::new();
+        let (ready_sender, mut ready_receiver) = mpsc::channel(1);
+        let (update_sender, _) = broadcast::channel(16);
+        let recorder = tokio::spawn(recorder(
+            token.clone(),
+            Arc::new(RwLock::new(config)),
+            SharedService::default(),
+            ready_sender,
+            update_sender.clone(),
+        ));
+        ready_receiver.recv().await.expect("ready");
+        let trigger = || {
+            assert!(update_sender
+                .send(Update::Event(Event::WatchdogTrigger))
+                .is_ok());
+        };
+
+        // The first event is sent at once, and the events until the retry are aggregated
+        trigger();
+        mock.wait(1).await;
+        assert!(update_sender.send(Update::Event(Event::Ready)).is_ok());
+        trigger();
+        trigger();
+        let requests = mock.wait(2).await;
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(requests[1].method, Method::POST);
+        assert_eq!(requests[1].body["count"], 3);
+
+        // Later events update the count of the created event once the interval has passed
+        trigger();
+        let requests = mock.wait(3).await;
+        assert_eq!(requests[2].method, Method::PATCH);
+        assert_eq!(requests[2].body["count"], 4);
+        time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(mock.requests().len(), 3);
+
+        token.cancel();
+        recorder.await.expect("join").expect("recorder");
+    }
+}
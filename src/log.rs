@@ -0,0 +1,80 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::error::Error;
+
+/// Writes log records to standard error, or to `ADAPTER_LOG_FILE`, which can be reopened after
+/// it has been rotated
+#[derive(Clone)]
+pub struct LogWriter {
+    file: Option<(PathBuf, Arc<Mutex<File>>)>,
+}
+
+impl LogWriter {
+    pub fn open(path: Option<&Path>) -> Result<Self, Error> {
+        let file = match path {
+            Some(path) => Some((path.into(), Arc::new(Mutex::new(open(path)?)))),
+            None => None,
+        };
+        Ok(LogWriter { file })
+    }
+
+    /// Opens the file again by its path, and returns whether there is one
+    pub fn reopen(&self) -> Result<bool, Error> {
+        let Some((path, file)) = &self.file else {
+            return Ok(false);
+        };
+        let reopened = open(path)?;
+        *file.lock().unwrap_or_else(PoisonError::into_inner) = reopened;
+        Ok(true)
+    }
+}
+
+fn open(path: &Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|error| Error::LogFileOpen(path.into(), error))
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogOutput;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        match &self.file {
+            Some((_, file)) => LogOutput::File(file.clone()),
+            None => LogOutput::Stderr(io::stderr()),
+        }
+    }
+}
+
+pub enum LogOutput {
+    Stderr(io::Stderr),
+    File(Arc<Mutex<File>>),
+}
+
+impl Write for LogOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stderr(stderr) => stderr.write(buf),
+            Self::File(file) => file
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stderr(stderr) => stderr.flush(),
+            Self::File(file) => file.lock().unwrap_or_else(PoisonError::into_inner).flush(),
+        }
+    }
+}
@@ -1,66 +1,184 @@
 #![warn(clippy::pedantic)]
 #![warn(clippy::unwrap_used)]
 #![warn(clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
 
-use std::{io, panic, process::exit, sync::Arc};
+use std::{future, panic, path::Path, pin::Pin, process::exit, sync::Arc};
 
+use clap::{CommandFactory, FromArgMatches};
 use const_format::concatcp;
-use envconfig::Envconfig;
+use nix::sys::signal::{self as nix_signal, Signal};
 use tokio::{
     runtime::{self, UnhandledPanic},
     signal::{self, unix::SignalKind},
-    sync::{mpsc, RwLock},
+    sync::{broadcast, mpsc, oneshot, RwLock},
     task::JoinSet,
+    time,
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use tracing_subscriber::{layer::SubscriberExt, reload, Registry};
 use valuable::Valuable;
 
 use crate::{
-    config::{Configuration, SharedConfiguration},
-    error::Error,
-    server::{http, uds},
-    status::{Change, SharedStatus, Status},
+    cli::{Cli, Command, ConfigCommand, RunArguments, SourceArguments},
+    command::{check, dump, notify, probe},
+    config::{Configuration, Reloader, SharedConfiguration},
+    error::{Error, EXIT_CONFIG},
+    log::LogWriter,
+    server::{admin, grpc, http, uds},
+    service::{Service, SharedService},
+    signals::SignalAction,
+    status::{Change, ChangeOperation, Condition, Reason, SharedStatus, Status},
     timer::{startup, watchdog},
+    validation::Severity,
 };
 
+mod cli;
+mod command {
+    pub mod check;
+    pub mod dump;
+    pub mod notify;
+    pub mod probe;
+}
 mod config;
 mod error;
 mod event;
+mod job;
+mod kubernetes {
+    pub mod client;
+    pub mod condition;
+    pub mod events;
+}
+mod log;
 mod message;
+mod service;
+mod shutdown;
+mod signals;
 mod status;
 mod server {
+    pub mod admin;
+    pub mod grpc;
     pub mod http;
     pub mod uds;
 }
+mod supervisor;
+#[cfg(test)]
+mod testing;
+mod source {
+    pub mod file;
+    pub mod unit;
+}
 mod timer {
     pub mod startup;
     pub mod watchdog;
 }
+mod validation;
 
 fn main() {
-    if let Err(error) = adapter() {
-        error!("{}", error);
-        exit(1);
+    let mut matches = Cli::command().get_matches();
+    if matches.subcommand().is_none() {
+        matches = Cli::command().get_matches_from([env!("CARGO_BIN_NAME"), "run"]);
+    }
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    let result = match cli.command {
+        Some(Command::Run(arguments)) => {
+            let options = cli::options(&matches);
+            let (config, problems) = load(&arguments.sources, &options);
+            if problems
+                .iter()
+                .any(|problem| problem.severity == Severity::Error)
+            {
+                for problem in problems {
+                    eprintln!("{problem}");
+                }
+                exit(EXIT_CONFIG);
+            }
+            let log_file = config.log_file.as_ref().map(|path| Path::new(&*path.0));
+            let log_writer = LogWriter::open(log_file).unwrap_or_else(|error| {
+                eprintln!("{error}");
+                exit(error.exit_code());
+            });
+            match adapter(config, &problems, &arguments, &options, &log_writer) {
+                Ok(()) => return,
+                // The supervised service and the job have already been logged
+                Err(error @ (Error::ServiceExit(_) | Error::JobComplete(_))) => {
+                    exit(error.exit_code())
+                }
+                Err(error) => {
+                    error!("{}", error);
+                    exit(error.exit_code());
+                }
+            }
+        }
+        Some(Command::Probe(arguments)) => probe::probe(&arguments),
+        Some(Command::Notify(arguments)) => notify::notify(&arguments),
+        Some(Command::Config {
+            command: ConfigCommand::Check(arguments),
+        }) => Ok(check::check(&load(&arguments, &cli::options(&matches)).1)),
+        Some(Command::Config {
+            command: ConfigCommand::Dump(arguments),
+        }) => dump::dump(
+            &load(&arguments.sources, &cli::options(&matches)).0,
+            arguments.format,
+        ),
+        None => unreachable!("The run subcommand is the default"),
+    };
+    match result {
+        Ok(true) => exit(0),
+        Ok(false) => exit(1),
+        Err(error) => {
+            eprintln!("{error}");
+            exit(1);
+        }
     }
 }
 
+/// Loads and validates the configuration or exits, since logging is not set up before it is
+/// loaded
+fn load(
+    arguments: &SourceArguments,
+    options: &[(String, String)],
+) -> (Configuration, Vec<validation::Problem>) {
+    let (config, mut problems) = Configuration::load(
+        arguments.config.as_deref(),
+        arguments.unit_file.as_deref(),
+        options,
+    )
+    .unwrap_or_else(|error| {
+        eprintln!("{error}");
+        exit(EXIT_CONFIG);
+    });
+    problems.extend(validation::validate(&config));
+    (config, problems)
+}
+
 #[allow(clippy::too_many_lines)]
-fn adapter() -> Result<(), Error> {
+fn adapter(
+    config: Configuration,
+    warnings: &[validation::Problem],
+    arguments: &RunArguments,
+    options: &[(String, String)],
+    log_writer: &LogWriter,
+) -> Result<(), Error> {
     let body = async {
-        let config = Configuration::init_from_env().map_err(Error::Config)?;
+        let started = admin::Started::now();
         let status = Status::from_config(&config);
 
-        if config.log {
-            let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        let (filter, log_handle) = reload::Layer::new(config::log_filter(config.log));
+        let subscriber = Registry::default().with(filter).with(
+            tracing_subscriber::fmt::layer()
                 .json()
                 .flatten_event(true)
-                .with_writer(io::stderr)
-                .finish();
-            tracing::subscriber::set_global_default(subscriber).map_err(Error::TraceSubscribe)?;
-        }
+                .with_writer(log_writer.clone()),
+        );
+        tracing::subscriber::set_global_default(subscriber).map_err(Error::TraceSubscribe)?;
 
         tracing::info!(config = config.as_value(), "Initial configuration");
+        for warning in warnings {
+            warn!("{}", warning.message);
+        }
 
         let (ready_sender, mut ready_receiver) = mpsc::channel(config.channel_size);
         let (shutdown_sender, mut shutdown_receiver) = mpsc::channel(config.channel_size);
@@ -68,16 +186,32 @@ fn adapter() -> Result<(), Error> {
         let (event_sender, event_receiver) = mpsc::channel(config.channel_size);
         let (config_sender, config_receiver) = mpsc::channel(config.channel_size);
         let (status_sender, status_receiver) = mpsc::channel(config.channel_size);
+        let (update_sender, _) = broadcast::channel(config.channel_size);
+        let (signal_sender, signal_receiver) = mpsc::channel(config.channel_size);
+        let (start_sender, start_receiver) = oneshot::channel();
 
         let token = CancellationToken::new();
+        let reloader = Reloader::new(
+            arguments.sources.config.as_deref(),
+            arguments.sources.unit_file.as_deref(),
+            options,
+            config_sender.clone(),
+        );
+
+        // Like `RuntimeMaxSec=`, which cannot change while the adapter runs
+        let runtime = time::sleep(config.unit_runtime_max_sec.into());
+        tokio::pin!(runtime);
 
         let config: SharedConfiguration = Arc::new(RwLock::new(config));
         let status: SharedStatus = Arc::new(RwLock::new(status));
+        let service: SharedService = Arc::new(RwLock::new(Service::default()));
 
         let mut handles = JoinSet::new();
+        // Listeners shut down one after another in this order, before the other tasks
+        let mut listeners = Vec::new();
         macro_rules! spawn_task {
-            ($task: expr, $name: expr, $shutdown: expr) => {
-                handles.spawn(async move {
+            ($handles: expr, $task: expr, $name: expr, $shutdown: expr) => {
+                $handles.spawn(async move {
                     let result = $task.await;
                     if let Err(error) = result {
                         $shutdown
@@ -89,42 +223,120 @@ fn adapter() -> Result<(), Error> {
             };
         }
 
-        let token_clone = token.clone();
+        let listener_token = token.child_token();
+        let token_clone = listener_token.clone();
+        let service_clone = service.clone();
         let config_clone = config.clone();
         let ready_sender_clone = ready_sender.clone();
         let config_sender_clone = config_sender.clone();
         let event_sender_clone = event_sender.clone();
+        let update_sender_clone = update_sender.clone();
         let shutdown_sender_clone = shutdown_sender.clone();
+        let mut listener = JoinSet::new();
         spawn_task!(
+            listener,
             uds::server(
                 token_clone,
                 config_clone,
+                service_clone,
                 ready_sender_clone,
                 config_sender_clone,
                 event_sender_clone,
+                update_sender_clone,
             ),
             "UDS server",
             shutdown_sender_clone
         );
+        listeners.push((listener_token, listener));
 
-        let token_clone = token.clone();
+        let listener_token = token.child_token();
+        let token_clone = listener_token.clone();
         let config_clone = config.clone();
         let status_clone = status.clone();
         let ready_sender_clone = ready_sender.clone();
+        let update_sender_clone = update_sender.clone();
         let shutdown_sender_clone = shutdown_sender.clone();
+        let mut listener = JoinSet::new();
         spawn_task!(
-            http::server(token_clone, config_clone, status_clone, ready_sender_clone),
+            listener,
+            grpc::server(
+                token_clone,
+                config_clone,
+                status_clone,
+                ready_sender_clone,
+                update_sender_clone,
+            ),
+            "gRPC server",
+            shutdown_sender_clone
+        );
+        listeners.push((listener_token, listener));
+
+        let listener_token = token.child_token();
+        let token_clone = listener_token.clone();
+        let config_clone = config.clone();
+        let status_clone = status.clone();
+        let service_clone = service.clone();
+        let ready_sender_clone = ready_sender.clone();
+        let update_sender_clone = update_sender.clone();
+        let shutdown_sender_clone = shutdown_sender.clone();
+        let mut listener = JoinSet::new();
+        spawn_task!(
+            listener,
+            http::server(
+                token_clone,
+                config_clone,
+                status_clone,
+                service_clone,
+                ready_sender_clone,
+                update_sender_clone,
+            ),
             "HTTP server",
             shutdown_sender_clone
         );
+        listeners.push((listener_token, listener));
+
+        let listener_token = token.child_token();
+        let token_clone = listener_token.clone();
+        let config_clone = config.clone();
+        let status_clone = status.clone();
+        let service_clone = service.clone();
+        let ready_sender_clone = ready_sender.clone();
+        let config_sender_clone = config_sender.clone();
+        let event_sender_clone = event_sender.clone();
+        let status_sender_clone = status_sender.clone();
+        let update_sender_clone = update_sender.clone();
+        let reloader_clone = reloader.clone();
+        let shutdown_sender_clone = shutdown_sender.clone();
+        let mut listener = JoinSet::new();
+        spawn_task!(
+            listener,
+            admin::server(
+                token_clone,
+                config_clone,
+                status_clone,
+                service_clone,
+                ready_sender_clone,
+                config_sender_clone,
+                event_sender_clone,
+                status_sender_clone,
+                update_sender_clone,
+                reloader_clone,
+                started,
+            ),
+            "admin server",
+            shutdown_sender_clone
+        );
+        listeners.push((listener_token, listener));
 
         let token_clone = token.clone();
         let config_clone = config.clone();
         let ready_sender_clone = ready_sender.clone();
         let watchdog_sender_clone = watchdog_sender.clone();
         let status_sender_clone = status_sender.clone();
+        let update_sender_clone = update_sender.clone();
         let shutdown_sender_clone = shutdown_sender.clone();
         spawn_task!(
+            handles,
             event::event_listener(
                 token_clone,
                 config_clone,
@@ -132,22 +344,26 @@ fn adapter() -> Result<(), Error> {
                 ready_sender_clone,
                 watchdog_sender_clone,
                 status_sender_clone,
+                update_sender_clone,
             ),
             "event listener",
             shutdown_sender_clone
         );
 
         let token_clone = token.clone();
+        let service_clone = service.clone();
         let config_clone = config.clone();
         let status_clone = status.clone();
         let event_sender_clone = event_sender.clone();
         let ready_sender_clone = ready_sender.clone();
         let shutdown_sender_clone = shutdown_sender.clone();
         spawn_task!(
+            handles,
             startup::timer(
                 token_clone,
                 config_clone,
                 status_clone,
+                service_clone,
                 ready_sender_clone,
                 event_sender_clone
             ),
@@ -156,14 +372,17 @@ fn adapter() -> Result<(), Error> {
         );
 
         let token_clone = token.clone();
+        let service_clone = service.clone();
         let config_clone = config.clone();
         let ready_sender_clone = ready_sender.clone();
         let event_sender_clone = event_sender.clone();
         let shutdown_sender_clone = shutdown_sender.clone();
         spawn_task!(
+            handles,
             watchdog::timer(
                 token_clone,
                 config_clone,
+                service_clone,
                 watchdog_receiver,
                 ready_sender_clone,
                 event_sender_clone
@@ -175,13 +394,16 @@ fn adapter() -> Result<(), Error> {
         let token_clone = token.clone();
         let status_clone = status.clone();
         let ready_sender_clone = ready_sender.clone();
+        let update_sender_clone = update_sender.clone();
         let shutdown_sender_clone = shutdown_sender.clone();
         spawn_task!(
+            handles,
             status::status_writer(
                 token_clone,
                 status_clone,
                 status_receiver,
-                ready_sender_clone
+                ready_sender_clone,
+                update_sender_clone,
             ),
             "status writer",
             shutdown_sender_clone
@@ -190,19 +412,124 @@ fn adapter() -> Result<(), Error> {
         let token_clone = token.clone();
         let config_clone = config.clone();
         let ready_sender_clone = ready_sender.clone();
+        let watchdog_sender_clone = watchdog_sender.clone();
         let shutdown_sender_clone = shutdown_sender.clone();
         spawn_task!(
+            handles,
             config::config_writer(
                 token_clone,
                 config_clone,
                 config_receiver,
-                ready_sender_clone
+                ready_sender_clone,
+                watchdog_sender_clone,
+                log_handle,
             ),
             "config writer",
             shutdown_sender_clone
         );
 
-        let num_handles = handles.len();
+        if config.read().await.job {
+            let token_clone = token.clone();
+            let config_clone = config.clone();
+            let service_clone = service.clone();
+            let ready_sender_clone = ready_sender.clone();
+            let update_sender_clone = update_sender.clone();
+            let shutdown_sender_clone = shutdown_sender.clone();
+            spawn_task!(
+                handles,
+                job::watcher(
+                    token_clone,
+                    config_clone,
+                    service_clone,
+                    ready_sender_clone,
+                    update_sender_clone,
+                ),
+                "job watcher",
+                shutdown_sender_clone
+            );
+        }
+
+        if config.read().await.kubernetes_condition.is_some() {
+            let token_clone = token.clone();
+            let config_clone = config.clone();
+            let status_clone = status.clone();
+            let service_clone = service.clone();
+            let ready_sender_clone = ready_sender.clone();
+            let update_sender_clone = update_sender.clone();
+            let shutdown_sender_clone = shutdown_sender.clone();
+            spawn_task!(
+                handles,
+                kubernetes::condition::patcher(
+                    token_clone,
+                    config_clone,
+                    status_clone,
+                    service_clone,
+                    ready_sender_clone,
+                    update_sender_clone,
+                ),
+                "Pod condition",
+                shutdown_sender_clone
+            );
+        }
+
+        if !config.read().await.kubernetes_events.is_empty() {
+            let token_clone = token.clone();
+            let config_clone = config.clone();
+            let service_clone = service.clone();
+            let ready_sender_clone = ready_sender.clone();
+            let update_sender_clone = update_sender.clone();
+            let shutdown_sender_clone = shutdown_sender.clone();
+            spawn_task!(
+                handles,
+                kubernetes::events::recorder(
+                    token_clone,
+                    config_clone,
+                    service_clone,
+                    ready_sender_clone,
+                    update_sender_clone,
+                ),
+                "Kubernetes events",
+                shutdown_sender_clone
+            );
+        }
+
+        let supervising = !arguments.command.is_empty();
+        if supervising {
+            let token_clone = token.clone();
+            let config_clone = config.clone();
+            let service_clone = service.clone();
+            let command = Arc::from(arguments.command.as_slice());
+            let ready_sender_clone = ready_sender.clone();
+            let watchdog_sender_clone = watchdog_sender.clone();
+            let event_sender_clone = event_sender.clone();
+            let status_sender_clone = status_sender.clone();
+            let update_sender_clone = update_sender.clone();
+            let shutdown_sender_clone = shutdown_sender.clone();
+            spawn_task!(
+                handles,
+                supervisor::supervisor(
+                    token_clone,
+                    config_clone,
+                    service_clone,
+                    command,
+                    start_receiver,
+                    signal_receiver,
+                    ready_sender_clone,
+                    watchdog_sender_clone,
+                    event_sender_clone,
+                    status_sender_clone,
+                    update_sender_clone,
+                ),
+                "supervisor",
+                shutdown_sender_clone
+            );
+        }
+
+        let num_handles = handles.len()
+            + listeners
+                .iter()
+                .map(|(_, listener): &(_, JoinSet<_>)| listener.len())
+                .sum::<usize>();
         let status_sender_clone = status_sender.clone();
         tokio::spawn(async move {
             for _ in 0..num_handles {
@@ -211,13 +538,21 @@ fn adapter() -> Result<(), Error> {
             info!("Adapter ready");
             status_sender_clone
                 .send(Change {
-                    healthz: status::ChangeOperation::Set(true),
-                    livez: status::ChangeOperation::Keep,
-                    readyz: status::ChangeOperation::Keep,
+                    healthz: ChangeOperation::Set(
+                        true,
+                        Reason {
+                            condition: Condition::Adapter,
+                            message: String::from("The adapter is ready"),
+                        },
+                    ),
+                    livez: ChangeOperation::Keep,
+                    readyz: ChangeOperation::Keep,
                 })
                 .await
                 .map_err(Error::StatusChannelSend)
                 .expect("Could not send ready status change");
+            // The supervisor is gone if it is not supervising or has failed
+            let _ = start_sender.send(());
         });
 
         macro_rules! unix_signal (($e: expr) => {signal::unix::signal($e).map_err(Error::Signal)?});
@@ -231,27 +566,108 @@ fn adapter() -> Result<(), Error> {
         let mut user_defined1 = unix_signal!(SignalKind::user_defined1());
         let mut user_defined2 = unix_signal!(SignalKind::user_defined2());
 
-        let result = tokio::select! {
-            _ = alarm.recv() => Ok(()),
-            _ = hangup.recv() => Ok(()),
-            _ = interrupt.recv() => Ok(()),
-            _ = pipe.recv() => Ok(()),
-            _ = quit.recv() => Ok(()),
-            _ = terminate.recv() => Ok(()),
-            _ = user_defined1.recv() => Ok(()),
-            _ = user_defined2.recv() => Ok(()),
-            error = shutdown_receiver.recv() => {
-                match error {
-                    Some(error) => Err(error),
-                    None => Err(Error::ShutdownChannelClosed),
+        // Started by the `drain` action, after which the adapter shuts down or stops the
+        // supervised service with the signal that started it
+        let mut draining = None;
+        let mut drained = None;
+        let result = loop {
+            let drain = async {
+                match &mut draining {
+                    Some((_, drain)) => Pin::as_mut(drain).await,
+                    None => future::pending().await,
+                }
+            };
+            let signal = tokio::select! {
+                () = &mut runtime => break Err(Error::RuntimeTimeout),
+                result = drain => {
+                    drained = draining.take().map(|(signal, _)| signal);
+                    if let Err(error) = result {
+                        break Err(error);
+                    }
+                    match drained {
+                        Some(signal) if supervising => {
+                            let _ = signal_sender.send(stop_signal(signal)).await;
+                            continue;
+                        }
+                        _ => break Ok(()),
+                    }
+                },
+                _ = alarm.recv() => Signal::SIGALRM,
+                _ = hangup.recv() => Signal::SIGHUP,
+                _ = interrupt.recv() => Signal::SIGINT,
+                _ = pipe.recv() => Signal::SIGPIPE,
+                _ = quit.recv() => Signal::SIGQUIT,
+                _ = terminate.recv() => Signal::SIGTERM,
+                _ = user_defined1.recv() => Signal::SIGUSR1,
+                _ = user_defined2.recv() => Signal::SIGUSR2,
+                error = shutdown_receiver.recv() => {
+                    match error {
+                        Some(error) => break Err(error),
+                        None => break Err(Error::ShutdownChannelClosed),
+                    }
+                },
+            };
+            let action = config.read().await.signal_actions.action(signal);
+            info!(signal = signal.as_str(), %action, "Received signal");
+            match action {
+                SignalAction::Reload => {
+                    reloader.reload().await?;
+                }
+                SignalAction::Dump => {
+                    let state = admin::state(&config, &status, &service, started).await;
+                    eprintln!("{state}");
+                }
+                SignalAction::Reopen => match log_writer.reopen() {
+                    Ok(true) => info!("Reopened log file"),
+                    Ok(false) => info!("No log file to reopen"),
+                    Err(error) => error!("{}", error),
+                },
+                SignalAction::Ignore => {}
+                SignalAction::Drain if drained.is_none() => {
+                    if draining.is_none() {
+                        let drain = shutdown::drain(
+                            config.clone(),
+                            service.clone(),
+                            status_sender.clone(),
+                            update_sender.clone(),
+                            supervising,
+                        );
+                        draining = Some((signal, Box::pin(drain)));
+                    }
+                }
+                // The service stops on its own terms, and its exit shuts down the adapter
+                SignalAction::Drain | SignalAction::Shutdown if supervising => {
+                    // A supervisor that has stopped has sent its error to the shutdown channel
+                    let _ = signal_sender.send(stop_signal(signal)).await;
                 }
-            },
+                SignalAction::Drain | SignalAction::Shutdown => break Ok(()),
+            }
         };
 
-        token.cancel();
-
-        while let Some(result) = handles.join_next().await {
-            result.map_err(Error::Join)?;
+        let stop_timeout = config.read().await.unit_timeout_stop_sec;
+        let shutdown = async {
+            for (listener_token, mut listener) in listeners {
+                listener_token.cancel();
+                while let Some(result) = listener.join_next().await {
+                    result.map_err(Error::Join)?;
+                }
+            }
+            token.cancel();
+            while let Some(result) = handles.join_next().await {
+                result.map_err(Error::Join)?;
+            }
+            Ok(())
+        };
+        if let Ok(shutdown) = time::timeout(stop_timeout.into(), shutdown).await {
+            shutdown?;
+        } else {
+            token.cancel();
+            warn!("The adapter did not shut down within ADAPTER_UNIT_TIMEOUT_STOP_SEC");
+            if supervising {
+                if let Some(pid) = service.read().await.main_process() {
+                    let _ = nix_signal::kill(pid, Signal::SIGKILL);
+                }
+            }
         }
 
         result
@@ -264,3 +680,12 @@ fn adapter() -> Result<(), Error> {
         .expect("Failed building the Runtime")
         .block_on(body)
 }
+
+/// The signal that stops the supervised service, which is the signal the adapter has received if
+/// it is meant to stop a process
+fn stop_signal(signal: Signal) -> Signal {
+    match signal {
+        Signal::SIGINT | Signal::SIGQUIT | Signal::SIGTERM => signal,
+        _ => Signal::SIGTERM,
+    }
+}
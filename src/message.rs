@@ -2,7 +2,10 @@ use std::str::FromStr;
 
 use valuable::Valuable;
 
-use crate::{config::Seconds, error::Error};
+use crate::{
+    config::Seconds,
+    error::{Error, EXIT_FAILURE},
+};
 
 #[derive(Clone)]
 pub enum Message {
@@ -27,14 +30,38 @@ pub enum Message {
     Barrier,
 }
 
-const SECOND_TO_MICROSECOND: f64 = 1_000_000.0;
+impl Message {
+    /// The key of the assignment, e.g. `READY`
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::Ready => "READY",
+            Self::Reloading => "RELOADING",
+            Self::Stopping => "STOPPING",
+            Self::MonotonicMicrosecond(_) => "MONOTONIC_USEC",
+            Self::Status(_) => "STATUS",
+            Self::NotifyAccess(_) => "NOTIFYACCESS",
+            Self::ErrorNumber(_) => "ERRNO",
+            Self::BusError(_) => "BUSERROR",
+            Self::ExitStatus(_) => "EXIT_STATUS",
+            Self::MainPID(_) => "MAINPID",
+            Self::Watchdog | Self::WatchdogTrigger => "WATCHDOG",
+            Self::WatchdogMicrosecond(_) => "WATCHDOG_USEC",
+            Self::ExtendTimeoutMicrosecond(_) => "EXTEND_TIMEOUT_USEC",
+            Self::FDStore => "FDSTORE",
+            Self::FDStoreRemove => "FDSTOREREMOVE",
+            Self::FDName(_) => "FDNAME",
+            Self::FDPoll => "FDPOLL",
+            Self::Barrier => "BARRIER",
+        }
+    }
+}
 
 impl FromStr for Message {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (key, value) = s.split_once('=').ok_or(Error::MessageSplit(s.into()))?;
         macro_rules! parse_message (($e: path) => {Ok($e(value.parse::<i32>().map_err(Error::MessageParseInt)?))});
-        macro_rules! micro_second (($e: path) => {Ok($e(Seconds(value.parse::<f64>().map_err(Error::MessageParseFloat)?/SECOND_TO_MICROSECOND)))});
+        macro_rules! micro_second (($e: path) => {Ok($e(Seconds::from_microseconds(value.parse::<u64>().map_err(Error::MessageParseInt)?)))});
 
         match (key, value) {
             ("READY", "1") => Ok(Self::Ready),
@@ -45,7 +72,15 @@ impl FromStr for Message {
             ("NOTIFYACCESS", _) => Ok(Self::NotifyAccess(value.parse()?)),
             ("ERRNO", _) => parse_message!(Self::ErrorNumber),
             ("BUSERROR", _) => Ok(Self::BusError(value.into())),
-            ("EXIT_STATUS", _) => parse_message!(Self::ExitStatus),
+            // Only the lowest 8 bits of an exit status remain, so any other status is a failure
+            ("EXIT_STATUS", _) => {
+                let status = value.parse::<i32>().map_err(Error::MessageParseInt)?;
+                Ok(Self::ExitStatus(if (0..=255).contains(&status) {
+                    status
+                } else {
+                    EXIT_FAILURE
+                }))
+            }
             ("MAINPID", _) => parse_message!(Self::MainPID),
             ("WATCHDOG", "1") => Ok(Self::Watchdog),
             ("WATCHDOG", "trigger") => Ok(Self::WatchdogTrigger),
@@ -63,7 +98,7 @@ impl FromStr for Message {
 
 impl From<Message> for String {
     fn from(value: Message) -> Self {
-        macro_rules! as_microsecond(($k: expr, $d: expr) => {format!("{}={}", $k, $d.0 * SECOND_TO_MICROSECOND)});
+        macro_rules! as_microsecond(($k: expr, $d: expr) => {format!("{}={}", $k, $d.as_microseconds())});
         match value {
             Message::Ready => String::from("READY=1"),
             Message::Reloading => String::from("RELOADING=1"),
@@ -72,12 +107,7 @@ impl From<Message> for String {
                 as_microsecond!("MONOTONIC_USEC", timestamp)
             }
             Message::Status(status) => format!("STATUS={status}"),
-            Message::NotifyAccess(a) => match a {
-                NotifyAccess::None => String::from("NOTIFYACCESS=none"),
-                NotifyAccess::Main => String::from("NOTIFYACCESS=main"),
-                NotifyAccess::Exec => String::from("NOTIFYACCESS=exec"),
-                NotifyAccess::All => String::from("NOTIFYACCESS=all"),
-            },
+            Message::NotifyAccess(access) => format!("NOTIFYACCESS={}", access.name()),
             Message::ErrorNumber(number) => format!("ERRNO={number}"),
             Message::BusError(error) => format!("BUSERROR={error}"),
             Message::ExitStatus(status) => format!("EXIT_STATUS={status}"),
@@ -97,7 +127,7 @@ impl From<Message> for String {
     }
 }
 
-#[derive(Clone, Valuable)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum NotifyAccess {
     None,
     Main,
@@ -105,6 +135,27 @@ pub enum NotifyAccess {
     All,
 }
 
+impl NotifyAccess {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Main => "main",
+            Self::Exec => "exec",
+            Self::All => "all",
+        }
+    }
+}
+
+impl Valuable for NotifyAccess {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(self.name())
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
 impl FromStr for NotifyAccess {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -117,3 +168,24 @@ impl FromStr for NotifyAccess {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit_status(value: &str) -> Option<i32> {
+        match format!("EXIT_STATUS={value}").parse() {
+            Ok(Message::ExitStatus(status)) => Some(status),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn exit_status_is_a_valid_exit_code() {
+        assert_eq!(exit_status("0"), Some(0));
+        assert_eq!(exit_status("255"), Some(255));
+        assert_eq!(exit_status("256"), Some(EXIT_FAILURE));
+        assert_eq!(exit_status("-1"), Some(EXIT_FAILURE));
+        assert_eq!(exit_status("failure"), None);
+    }
+}
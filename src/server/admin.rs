@@ -0,0 +1,517 @@
+use std::{
+    collections::BTreeMap, fmt, future::IntoFuture, net::SocketAddr, path::PathBuf, str::FromStr,
+    sync::Arc, time::Instant,
+};
+
+use axum::{
+    extract::{Path, Query, Request},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto::Builder, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use tokio::{
+    net::{TcpListener, UnixListener},
+    sync::{broadcast, mpsc::Sender},
+};
+use tokio_util::sync::CancellationToken;
+use tower_http::trace::{self, TraceLayer};
+use tracing::{info, instrument, warn};
+use valuable::Valuable;
+use valuable_serde::Serializable;
+
+use crate::{
+    cli::DumpFormat,
+    command::dump,
+    config::{ConfigSecret, Configuration, ConfigurationChange, Reloader, SharedConfiguration},
+    error::Error,
+    event::Event,
+    server::{http, uds},
+    service::SharedService,
+    status::{Change, ChangeOperation, Condition, Probe, Reason, SharedStatus, Update},
+};
+
+#[allow(clippy::too_many_arguments)]
+#[instrument(name = "Admin server", skip_all)]
+pub async fn server(
+    token: CancellationToken,
+    config: SharedConfiguration,
+    status: SharedStatus,
+    service: SharedService,
+    ready_sender: Sender<()>,
+    config_sender: Sender<ConfigurationChange>,
+    event_sender: Sender<Event>,
+    status_sender: Sender<Change>,
+    update_sender: broadcast::Sender<Update>,
+    reloader: Reloader,
+    started: Started,
+) -> Result<(), Error> {
+    let (address, notify_enabled) = {
+        let config_lock = config.read().await;
+        (config_lock.admin_address.clone(), config_lock.admin_notify)
+    };
+
+    let mut app = http::routes()
+        .route("/status", get(introspect))
+        .route("/config", get(dump_config))
+        .route("/reload", post(reload))
+        .route(
+            "/override/:probe",
+            put(set_override).delete(remove_override),
+        );
+    if notify_enabled {
+        app = app.route("/notify", post(notify));
+    }
+    let app = app
+        .layer(middleware::from_fn(authorize))
+        .layer(Extension(status))
+        .layer(Extension(service))
+        .layer(Extension(started))
+        .layer(Extension(reloader))
+        .layer(Extension(config.clone()))
+        .layer(Extension(config_sender))
+        .layer(Extension(event_sender))
+        .layer(Extension(status_sender))
+        .layer(Extension(update_sender))
+        .layer(Extension(token.clone()))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(trace::DefaultMakeSpan::new().include_headers(true))
+                .on_response(trace::DefaultOnResponse::new().include_headers(true)),
+        );
+
+    match address.listen {
+        None => {
+            info!("Admin server disabled");
+            ready_sender
+                .send(())
+                .await
+                .map_err(Error::ReadyChannelSend)?;
+            Ok(())
+        }
+        Some(Listen::Tcp(address)) => {
+            let listener = TcpListener::bind(address)
+                .await
+                .map_err(Error::AdminBindAddress)?;
+
+            info!("Admin server ready");
+            ready_sender
+                .send(())
+                .await
+                .map_err(Error::ReadyChannelSend)?;
+
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async move {
+                    token.cancelled().await;
+                    info!("Shutting down admin server");
+                })
+                .into_future()
+                .await
+                .map_err(Error::Admin)
+        }
+        Some(Listen::Unix(path)) => {
+            if path.exists() {
+                std::fs::remove_file(&path).map_err(Error::AdminBindAddress)?;
+            }
+            let listener = UnixListener::bind(&path).map_err(Error::AdminBindAddress)?;
+
+            info!("Admin server ready");
+            ready_sender
+                .send(())
+                .await
+                .map_err(Error::ReadyChannelSend)?;
+
+            serve_unix(token, listener, app).await
+        }
+    }
+}
+
+/// Serves HTTP on a Unix domain socket, which `axum::serve` does not support
+async fn serve_unix(
+    token: CancellationToken,
+    listener: UnixListener,
+    app: Router,
+) -> Result<(), Error> {
+    let builder = Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+
+    loop {
+        let (stream, _) = tokio::select! {
+            () = token.cancelled() => break,
+            result = listener.accept() => result,
+        }
+        .map_err(Error::Admin)?;
+        let service = TowerToHyperService::new(app.clone());
+        let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+        let connection = graceful.watch(connection.into_owned());
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                warn!("Admin connection failed: {}", error);
+            }
+        });
+    }
+
+    info!("Shutting down admin server");
+    graceful.shutdown().await;
+
+    Ok(())
+}
+
+/// When the adapter has started
+#[derive(Clone, Copy)]
+pub struct Started(DateTime<Utc>, Instant);
+
+impl Started {
+    pub fn now() -> Self {
+        Self(Utc::now(), Instant::now())
+    }
+}
+
+async fn introspect(
+    Extension(config): Extension<SharedConfiguration>,
+    Extension(status): Extension<SharedStatus>,
+    Extension(service): Extension<SharedService>,
+    Extension(started): Extension<Started>,
+) -> Response {
+    Json(state(&config, &status, &service, started).await).into_response()
+}
+
+/// Everything the adapter knows about itself and the service, as served by `/status`
+pub async fn state(
+    config: &SharedConfiguration,
+    status: &SharedStatus,
+    service: &SharedService,
+    Started(started, started_instant): Started,
+) -> serde_json::Value {
+    let status = status.read().await.clone();
+    let probes = BTreeMap::from([
+        ("healthz", ProbeReport::new(&status.healthz)),
+        ("livez", ProbeReport::new(&status.livez)),
+        ("readyz", ProbeReport::new(&status.readyz)),
+    ]);
+
+    let service = service.read().await;
+    let timestamp = |time: Option<DateTime<Utc>>| time.map(|time| time.to_rfc3339());
+    let service = ServiceReport {
+        main_pid: service.main_pid,
+        status: service.status.clone(),
+        errno: service.error_number,
+        buserror: service.bus_error.clone(),
+        exit_status: service.exit_status,
+        restarts: service.restarts,
+        messages: service.messages.clone(),
+        watchdog_ping: timestamp(service.watchdog_ping),
+        watchdog_deadline: timestamp(service.watchdog_deadline),
+        startup_deadline: timestamp(service.startup_deadline),
+    };
+
+    let config = config.read().await;
+    let report = Report {
+        timestamp: Utc::now().to_rfc3339(),
+        version: env!("CARGO_PKG_VERSION"),
+        started: started.to_rfc3339(),
+        uptime_seconds: started_instant.elapsed().as_secs_f64(),
+        probes,
+        service,
+        configuration: &config,
+    };
+    serde_json::to_value(Serializable::new(report)).expect("Could not serialize state")
+}
+
+async fn dump_config(
+    Extension(config): Extension<SharedConfiguration>,
+    Query(parameters): Query<Vec<(String, String)>>,
+) -> Response {
+    let format = parameters
+        .iter()
+        .rev()
+        .find(|(key, _)| key == "format")
+        .map(|(_, format)| format);
+    let format = match format {
+        None => DumpFormat::Json,
+        Some(format) => match DumpFormat::from_str(format, false) {
+            Ok(format) => format,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Unrecognized format: {format}"),
+                )
+                    .into_response()
+            }
+        },
+    };
+    let content_type = match format {
+        DumpFormat::Env => "text/plain; charset=utf-8",
+        DumpFormat::Toml => "application/toml",
+        DumpFormat::Json => "application/json",
+    };
+    match dump::render(&*config.read().await, format) {
+        Ok(body) => ([(header::CONTENT_TYPE, content_type)], body).into_response(),
+        Err(error) => {
+            warn!("Could not dump configuration: {}", error);
+            (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
+
+async fn reload(Extension(reloader): Extension<Reloader>) -> impl IntoResponse {
+    match reloader.reload().await {
+        // The config writer applies the configuration right after it has been accepted
+        Ok(errors) if errors.is_empty() => (StatusCode::ACCEPTED, String::new()),
+        Ok(errors) => (StatusCode::UNPROCESSABLE_ENTITY, errors.join("\n")),
+        Err(error) => {
+            warn!("Could not reload configuration: {}", error);
+            (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+        }
+    }
+}
+
+async fn notify(
+    Extension(config): Extension<SharedConfiguration>,
+    Extension(service): Extension<SharedService>,
+    Extension(config_sender): Extension<Sender<ConfigurationChange>>,
+    Extension(event_sender): Extension<Sender<Event>>,
+    Extension(update_sender): Extension<broadcast::Sender<Update>>,
+    datagram: String,
+) -> impl IntoResponse {
+    match uds::process_datagram(
+        config,
+        service,
+        config_sender,
+        event_sender,
+        update_sender,
+        &datagram,
+        // The sender of an HTTP request is unknown, so its `MAINPID=` is not trusted
+        None,
+    )
+    .await
+    {
+        Ok(()) => (StatusCode::NO_CONTENT, String::new()),
+        Err(error) if error.is_message_error() => (StatusCode::BAD_REQUEST, error.to_string()),
+        Err(error) => {
+            warn!("Could not process notification: {}", error);
+            (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+        }
+    }
+}
+
+async fn set_override(
+    Extension(status_sender): Extension<Sender<Change>>,
+    Path(probe): Path<String>,
+    value: String,
+) -> impl IntoResponse {
+    let Ok(value) = value.trim().parse::<bool>() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Could not parse boolean from: {value}"),
+        );
+    };
+    let reason = Reason {
+        condition: Condition::Override,
+        message: format!("Overridden to {value} by an administrator"),
+    };
+    change_probe(&status_sender, &probe, ChangeOperation::Set(value, reason)).await
+}
+
+async fn remove_override(
+    Extension(status_sender): Extension<Sender<Change>>,
+    Path(probe): Path<String>,
+) -> impl IntoResponse {
+    let operation = ChangeOperation::Remove(Condition::Override);
+    change_probe(&status_sender, &probe, operation).await
+}
+
+async fn change_probe(
+    status_sender: &Sender<Change>,
+    probe: &str,
+    operation: ChangeOperation,
+) -> (StatusCode, String) {
+    let keep = || ChangeOperation::Keep;
+    let change = match probe {
+        "healthz" => Change {
+            healthz: operation,
+            livez: keep(),
+            readyz: keep(),
+        },
+        "livez" => Change {
+            healthz: keep(),
+            livez: operation,
+            readyz: keep(),
+        },
+        "readyz" => Change {
+            healthz: keep(),
+            livez: keep(),
+            readyz: operation,
+        },
+        _ => {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Unrecognized probe: {probe}"),
+            )
+        }
+    };
+    match status_sender.send(change).await {
+        Ok(()) => (StatusCode::NO_CONTENT, String::new()),
+        Err(error) => {
+            let error = Error::StatusChannelSend(error);
+            warn!("Could not change probe: {}", error);
+            (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+        }
+    }
+}
+
+/// Rejects requests without the configured bearer token
+async fn authorize(
+    Extension(config): Extension<SharedConfiguration>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let token = config.read().await.admin_token.clone();
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !authorized(token.as_ref(), authorization) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+/// Whether an `Authorization` header carries the token, if one is configured
+fn authorized(token: Option<&ConfigSecret>, authorization: Option<&str>) -> bool {
+    token.is_none_or(|token| {
+        authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|provided| token.matches(provided))
+    })
+}
+
+#[derive(Valuable)]
+struct Report<'a> {
+    timestamp: String,
+    version: &'static str,
+    started: String,
+    uptime_seconds: f64,
+    probes: BTreeMap<&'static str, ProbeReport>,
+    service: ServiceReport,
+    configuration: &'a Configuration,
+}
+
+#[derive(Valuable)]
+struct ProbeReport {
+    value: bool,
+    conditions: BTreeMap<String, CheckReport>,
+}
+
+impl ProbeReport {
+    fn new(probe: &Probe) -> Self {
+        ProbeReport {
+            value: probe.value(),
+            conditions: probe
+                .checks()
+                .map(|(condition, check)| {
+                    let report = CheckReport {
+                        passing: check.passing,
+                        reason: check.reason.clone(),
+                    };
+                    (condition.to_string(), report)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Valuable)]
+struct CheckReport {
+    passing: bool,
+    reason: String,
+}
+
+#[derive(Valuable)]
+struct ServiceReport {
+    main_pid: Option<i32>,
+    status: Option<String>,
+    errno: Option<i32>,
+    buserror: Option<String>,
+    exit_status: Option<i32>,
+    restarts: u32,
+    messages: BTreeMap<&'static str, u64>,
+    watchdog_ping: Option<String>,
+    watchdog_deadline: Option<String>,
+    startup_deadline: Option<String>,
+}
+
+/// Where a server listens, e.g. `127.0.0.1:8090` or `unix:/var/run/adapter/admin.sock`
+#[derive(Clone)]
+pub struct ListenAddress {
+    /// Nothing if the server is disabled
+    pub listen: Option<Listen>,
+    source: Arc<str>,
+}
+
+#[derive(Clone)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let listen = if s.is_empty() {
+            None
+        } else if let Some(path) = s.strip_prefix("unix:") {
+            Some(Listen::Unix(PathBuf::from(path)))
+        } else {
+            let address = s.parse().map_err(|_| Error::ParseListenAddress(s.into()))?;
+            Some(Listen::Tcp(address))
+        };
+        Ok(ListenAddress {
+            listen,
+            source: s.into(),
+        })
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Valuable for ListenAddress {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(&self.source)
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_is_required_if_configured() {
+        let token: ConfigSecret = "hunter2".parse().expect("token");
+        assert!(authorized(Some(&token), Some("Bearer hunter2")));
+        assert!(!authorized(Some(&token), Some("Bearer hunter3")));
+        assert!(!authorized(Some(&token), Some("Bearer hunter")));
+        assert!(!authorized(Some(&token), Some("Bearer hunter22")));
+        assert!(!authorized(Some(&token), Some("hunter2")));
+        assert!(!authorized(Some(&token), None));
+        assert!(authorized(None, None));
+        assert!(authorized(None, Some("Bearer anything")));
+    }
+}
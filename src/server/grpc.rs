@@ -0,0 +1,114 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use tokio::{
+    net::TcpListener,
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::Sender,
+    },
+};
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Server;
+use tonic_health::{server::HealthReporter, ServingStatus};
+use tracing::{info, instrument};
+
+use crate::{
+    config::SharedConfiguration,
+    error::Error,
+    status::{SharedStatus, Status, Update},
+};
+
+type Probe = fn(&Status) -> bool;
+
+/// Health checking service names and the probes they report
+const SERVICES: [(&str, Probe); 6] = [
+    ("", |status| {
+        status.healthz.value() && status.livez.value() && status.readyz.value()
+    }),
+    ("healthz", |status| status.healthz.value()),
+    ("livez", |status| status.livez.value()),
+    ("readyz", |status| status.readyz.value()),
+    ("liveness", |status| status.livez.value()),
+    ("readiness", |status| status.readyz.value()),
+];
+
+#[instrument(name = "gRPC server", skip_all)]
+pub async fn server(
+    token: CancellationToken,
+    config: SharedConfiguration,
+    status: SharedStatus,
+    ready_sender: Sender<()>,
+    update_sender: broadcast::Sender<Update>,
+) -> Result<(), Error> {
+    let Some(port) = config.read().await.grpc_port else {
+        info!("gRPC server disabled");
+        ready_sender
+            .send(())
+            .await
+            .map_err(Error::ReadyChannelSend)?;
+        return Ok(());
+    };
+
+    let (mut reporter, service) = tonic_health::server::health_reporter();
+
+    // Subscribe before reading the status so that no change is missed in between
+    let mut update_receiver = update_sender.subscribe();
+    let mut current = status.read().await.clone();
+    report(&mut reporter, None, &current).await;
+
+    let listener = TcpListener::bind((IpAddr::V4(Ipv4Addr::UNSPECIFIED), port))
+        .await
+        .map_err(Error::GrpcBindAddress)?;
+
+    info!("gRPC server ready");
+    ready_sender
+        .send(())
+        .await
+        .map_err(Error::ReadyChannelSend)?;
+
+    let token_clone = token.clone();
+    let mut reporter_clone = reporter.clone();
+    let serve = Server::builder()
+        .add_service(service)
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+            token_clone.cancelled().await;
+            info!("Shutting down gRPC server");
+            // Watch streams only end once their service is no longer registered
+            for (name, _) in SERVICES {
+                reporter_clone.clear_service_status(name).await;
+            }
+        });
+    tokio::pin!(serve);
+
+    loop {
+        let update = tokio::select! {
+            result = &mut serve => return result.map_err(Error::Grpc),
+            result = update_receiver.recv(), if !token.is_cancelled() => result,
+        };
+        let next = match update {
+            Ok(Update::Status(next)) => next,
+            Ok(_) => continue,
+            // Skipped updates are summarized by the current status
+            Err(RecvError::Lagged(_)) => status.read().await.clone(),
+            Err(RecvError::Closed) => return (&mut serve).await.map_err(Error::Grpc),
+        };
+        report(&mut reporter, Some(&current), &next).await;
+        current = next;
+    }
+}
+
+async fn report(reporter: &mut HealthReporter, previous: Option<&Status>, current: &Status) {
+    for (name, probe) in SERVICES {
+        let value = probe(current);
+        if previous.map(probe) == Some(value) {
+            continue;
+        }
+        let serving_status = if value {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+        reporter.set_service_status(name, serving_status).await;
+    }
+}
@@ -1,33 +1,63 @@
 use std::{
-    future::IntoFuture,
+    convert::Infallible,
+    future::{self, IntoFuture},
     net::{IpAddr, Ipv4Addr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
 
-use axum::{http::StatusCode, response::IntoResponse, routing::get, Extension, Json, Router};
+use axum::{
+    extract::Query,
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Extension, Json, Router,
+};
 use chrono::Utc;
-use tokio::{net::TcpListener, sync::mpsc::Sender};
+use futures_util::{stream, StreamExt};
+use tokio::{
+    net::TcpListener,
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::Sender,
+    },
+    time::sleep,
+};
 use tokio_util::sync::CancellationToken;
 use tower_http::trace::{self, TraceLayer};
 use tracing::{info, span, Instrument, Level};
 use valuable::Valuable;
 use valuable_serde::Serializable;
 
-use crate::{config::SharedConfiguration, error::Error, status::SharedStatus};
+use crate::{
+    config::{Seconds, SharedConfiguration},
+    error::Error,
+    event::Event,
+    service::SharedService,
+    status::{self, Condition, Probe, SharedStatus, Update},
+};
 
 pub async fn server(
     token: CancellationToken,
     config: SharedConfiguration,
     status: SharedStatus,
+    service: SharedService,
     ready_sender: Sender<()>,
+    update_sender: broadcast::Sender<Update>,
 ) -> Result<(), Error> {
     let span = span!(Level::INFO, "HTTP server",);
 
     let span_clone = span.clone();
-    let app = Router::new()
-        .route("/healthz", get(healthz))
-        .route("/livez", get(livez))
-        .route("/readyz", get(readyz))
+    let app = routes()
         .layer(Extension(status))
+        .layer(Extension(service))
+        .layer(Extension(config.clone()))
+        .layer(Extension(update_sender))
+        .layer(Extension(token.clone()))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(span_clone)
@@ -37,7 +67,7 @@ pub async fn server(
 
     let span_clone = span.clone();
     let bind_future = TcpListener::bind((
-        IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED),
         config.read().instrument(span_clone).await.port,
     ));
 
@@ -53,56 +83,618 @@ pub async fn server(
         .await
         .map_err(Error::ReadyChannelSend)?;
 
-    let span_clone = span.clone();
     axum::serve(listener, app)
-        .with_graceful_shutdown(async move {
-            let _guard = span.enter();
-            token.cancelled().instrument(span_clone).await;
-            info!("Shutting down HTTP server");
-        })
+        .with_graceful_shutdown(
+            async move {
+                token.cancelled().await;
+                info!("Shutting down HTTP server");
+            }
+            .instrument(span),
+        )
         .into_future()
         .await
         .map_err(Error::Http)
 }
 
+/// Routes served by both the probe and the admin listener
+pub fn routes() -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .route("/events", get(events))
+}
+
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
 macro_rules! status {
-    ($p: ident, $s: expr) => {{
-        let status = get_status($s).await;
-        let code = if status.$p {
-            StatusCode::OK
+    ($p: ident, $codes: ident, $format: ident, $s: expr, $u: expr, $t: expr, $c: expr, $v: expr, $h: expr, $q: expr) => {{
+        let query = match ProbeQuery::parse($q) {
+            Ok(query) => query,
+            Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+        };
+        if let Some(value) = query.wait {
+            let condition =
+                |status: &status::Status| status.$p.value_excluding(&query.exclude) == value;
+            wait_for(&$s, &$u, &$t, condition, query.timeout).await;
+        }
+        let (codes, format) = {
+            let config = $c.read().await;
+            (config.$codes.clone(), config.$format)
+        };
+        let status = $s.read().await.clone();
+        let code = codes.resolve(&status.$p, &query.exclude);
+        let format = if query.verbose {
+            ResponseFormat::Text
         } else {
-            StatusCode::SERVICE_UNAVAILABLE
+            ResponseFormat::negotiate(&$h, format)
         };
-        (code, Json(Serializable::new(status)))
+        match format {
+            ResponseFormat::Json => {
+                let restarts = $v.read().await.restarts;
+                let snapshot = snapshot(&status, &query.exclude, restarts);
+                (code, Json(Serializable::new(snapshot))).into_response()
+            }
+            ResponseFormat::Text if query.verbose => {
+                (code, verbose(stringify!($p), &status.$p, &query.exclude)).into_response()
+            }
+            ResponseFormat::Text if status.$p.value_excluding(&query.exclude) => {
+                (code, "ok\n").into_response()
+            }
+            ResponseFormat::Text => (code, "failed\n").into_response(),
+            ResponseFormat::Empty => code.into_response(),
+        }
     }};
 }
 
-async fn healthz(Extension(status): Extension<SharedStatus>) -> impl IntoResponse {
-    status!(healthz, status)
+async fn healthz(
+    Extension(status): Extension<SharedStatus>,
+    Extension(update_sender): Extension<broadcast::Sender<Update>>,
+    Extension(token): Extension<CancellationToken>,
+    Extension(config): Extension<SharedConfiguration>,
+    Extension(service): Extension<SharedService>,
+    headers: HeaderMap,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Response {
+    status!(
+        healthz,
+        http_healthz_codes,
+        http_healthz_format,
+        status,
+        update_sender,
+        token,
+        config,
+        service,
+        headers,
+        query
+    )
+}
+
+async fn livez(
+    Extension(status): Extension<SharedStatus>,
+    Extension(update_sender): Extension<broadcast::Sender<Update>>,
+    Extension(token): Extension<CancellationToken>,
+    Extension(config): Extension<SharedConfiguration>,
+    Extension(service): Extension<SharedService>,
+    headers: HeaderMap,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Response {
+    status!(
+        livez,
+        http_livez_codes,
+        http_livez_format,
+        status,
+        update_sender,
+        token,
+        config,
+        service,
+        headers,
+        query
+    )
+}
+
+async fn readyz(
+    Extension(status): Extension<SharedStatus>,
+    Extension(update_sender): Extension<broadcast::Sender<Update>>,
+    Extension(token): Extension<CancellationToken>,
+    Extension(config): Extension<SharedConfiguration>,
+    Extension(service): Extension<SharedService>,
+    headers: HeaderMap,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Response {
+    status!(
+        readyz,
+        http_readyz_codes,
+        http_readyz_format,
+        status,
+        update_sender,
+        token,
+        config,
+        service,
+        headers,
+        query
+    )
+}
+
+/// Query parameters of the probe endpoints
+struct ProbeQuery {
+    /// The probe value to wait for before responding
+    wait: Option<bool>,
+    /// How long to wait for
+    timeout: Duration,
+    /// Whether to list the conditions of the probe as text
+    verbose: bool,
+    /// Conditions to ignore for this request
+    exclude: Vec<Condition>,
+}
+
+impl ProbeQuery {
+    fn parse(parameters: Vec<(String, String)>) -> Result<Self, String> {
+        let mut query = ProbeQuery {
+            wait: None,
+            timeout: DEFAULT_WAIT_TIMEOUT,
+            verbose: false,
+            exclude: Vec::new(),
+        };
+        let parse_bool = |value: &str| {
+            value
+                .parse::<bool>()
+                .map_err(|_| format!("Could not parse boolean from: {value}"))
+        };
+        // Unrecognized parameters are ignored, as some clients add their own
+        for (key, value) in parameters {
+            match key.as_str() {
+                "wait" => query.wait = Some(parse_bool(&value)?),
+                "timeout" => {
+                    query.timeout = value
+                        .parse::<Seconds>()
+                        .map_err(|error| error.to_string())?
+                        .into();
+                }
+                "verbose" => query.verbose = value.is_empty() || parse_bool(&value)?,
+                "exclude" => {
+                    for condition in value.split(',') {
+                        let condition = condition
+                            .parse()
+                            .map_err(|error: Error| error.to_string())?;
+                        query.exclude.push(condition);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(query)
+    }
+}
+
+/// Lists the conditions of a probe in the style of the Kubernetes API server
+fn verbose(name: &str, probe: &Probe, excluded: &[Condition]) -> String {
+    let mut lines: Vec<String> = probe
+        .checks()
+        .map(|(condition, check)| {
+            let (sign, state) = if check.passing {
+                ('+', "ok")
+            } else {
+                ('-', "failed")
+            };
+            let exclusion = if excluded.contains(&condition) {
+                "excluded: "
+            } else {
+                ""
+            };
+            format!("[{sign}]{condition} {exclusion}{state}: {}", check.reason)
+        })
+        .collect();
+    let result = if probe.value_excluding(excluded) {
+        "passed"
+    } else {
+        "failed"
+    };
+    lines.push(format!("{name} check {result}\n"));
+    lines.join("\n")
 }
 
-async fn livez(Extension(status): Extension<SharedStatus>) -> impl IntoResponse {
-    status!(livez, status)
+/// Waits until the status satisfies the condition, the timeout expires or the server shuts down
+async fn wait_for(
+    status: &SharedStatus,
+    update_sender: &broadcast::Sender<Update>,
+    token: &CancellationToken,
+    condition: impl Fn(&status::Status) -> bool,
+    timeout: Duration,
+) {
+    // Subscribe before reading the status so that no change is missed in between
+    let mut receiver = update_sender.subscribe();
+    let changes = async {
+        while !condition(&*status.read().await) {
+            if let Err(RecvError::Closed) = receiver.recv().await {
+                break;
+            }
+        }
+    };
+    tokio::select! {
+        () = token.cancelled() => {}
+        () = changes => {}
+        () = sleep(timeout) => {}
+    }
 }
 
-async fn readyz(Extension(status): Extension<SharedStatus>) -> impl IntoResponse {
-    status!(readyz, status)
+async fn events(
+    Extension(status): Extension<SharedStatus>,
+    Extension(update_sender): Extension<broadcast::Sender<Update>>,
+    Extension(token): Extension<CancellationToken>,
+    Extension(service): Extension<SharedService>,
+) -> impl IntoResponse {
+    // Subscribe before reading the status so that no change is missed in between
+    let receiver = update_sender.subscribe();
+    let current = Update::Status(status.read().await.clone());
+
+    let updates = stream::unfold(
+        (receiver, status, token),
+        |(mut receiver, status, token)| async move {
+            let update = tokio::select! {
+                () = token.cancelled() => return None,
+                result = receiver.recv() => match result {
+                    Ok(update) => update,
+                    // Skipped updates are summarized by the current status
+                    Err(RecvError::Lagged(_)) => Update::Status(status.read().await.clone()),
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            Some((update, (receiver, status, token)))
+        },
+    );
+
+    let stream = stream::once(future::ready(current))
+        .chain(updates)
+        .then(move |update| {
+            let service = service.clone();
+            async move {
+                let restarts = service.read().await.restarts;
+                Ok::<_, Infallible>(sse_event(update, restarts))
+            }
+        });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
 }
 
-async fn get_status(status: SharedStatus) -> Status {
-    let status = status.read().await;
+fn sse_event(update: Update, restarts: u32) -> sse::Event {
+    let timestamp = Utc::now().to_rfc3339();
+    let event = sse::Event::default();
+    match update {
+        Update::Status(status) => {
+            event
+                .event("status")
+                .json_data(Serializable::new(snapshot(&status, &[], restarts)))
+        }
+        Update::Event(Event::Exit(status)) => {
+            event
+                .event("event")
+                .json_data(Serializable::new(ExitUpdate {
+                    timestamp,
+                    event: String::from("exit"),
+                    status,
+                }))
+        }
+        Update::Event(processed) => {
+            event
+                .event("event")
+                .json_data(Serializable::new(EventUpdate {
+                    timestamp,
+                    event: processed.to_string(),
+                }))
+        }
+        Update::Text(text) => event.event("text").json_data(Serializable::new(TextUpdate {
+            timestamp,
+            status: text,
+        })),
+    }
+    .expect("Could not serialize server-sent event")
+}
+
+pub fn snapshot(status: &status::Status, excluded: &[Condition], restarts: u32) -> Status {
     Status {
         timestamp: Utc::now().to_rfc3339(),
-        healthz: status.healthz,
-        livez: status.livez,
-        readyz: status.readyz,
+        healthz: status.healthz.value_excluding(excluded),
+        livez: status.livez.value_excluding(excluded),
+        readyz: status.readyz.value_excluding(excluded),
+        restarts,
     }
 }
 
 #[derive(Valuable)]
-struct Status {
+pub struct Status {
     timestamp: String,
     healthz: bool,
     livez: bool,
     readyz: bool,
+    /// Number of times the supervised service has restarted
+    restarts: u32,
+}
+
+#[derive(Valuable)]
+struct EventUpdate {
+    timestamp: String,
+    event: String,
+}
+
+#[derive(Valuable)]
+struct ExitUpdate {
+    timestamp: String,
+    event: String,
+    status: i32,
+}
+
+#[derive(Valuable)]
+struct TextUpdate {
+    timestamp: String,
+    status: String,
+}
+
+/// The body format of probe responses
+#[derive(Clone, Copy)]
+pub enum ResponseFormat {
+    Json,
+    Text,
+    Empty,
+}
+
+impl ResponseFormat {
+    /// Picks the first format in the `Accept` header that can be served, or the configured format
+    fn negotiate(headers: &HeaderMap, configured: Self) -> Self {
+        let Some(accept) = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return configured;
+        };
+        for media_type in accept.split(',') {
+            match media_type.split(';').next().unwrap_or_default().trim() {
+                "application/json" => return Self::Json,
+                "text/plain" => return Self::Text,
+                "*/*" => return configured,
+                _ => {}
+            }
+        }
+        configured
+    }
+}
+
+impl Valuable for ResponseFormat {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(match self {
+            Self::Json => "json",
+            Self::Text => "text",
+            Self::Empty => "empty",
+        })
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+impl FromStr for ResponseFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "text" => Ok(Self::Text),
+            "empty" => Ok(Self::Empty),
+            _ => Err(Error::ParseResponseFormat(s.into())),
+        }
+    }
+}
+
+/// The status codes of probe responses, e.g. `pass=200,fail=503,stopping=429`
+#[derive(Clone)]
+pub struct ResponseCodes {
+    codes: Arc<[(ResponseState, StatusCode)]>,
+    source: Arc<str>,
+}
+
+#[derive(Clone, Copy)]
+enum ResponseState {
+    Pass,
+    Fail,
+    /// The probe fails because of this condition
+    Failing(Condition),
+}
+
+impl ResponseCodes {
+    fn resolve(&self, probe: &Probe, excluded: &[Condition]) -> StatusCode {
+        let passing = probe.value_excluding(excluded);
+        let failing = |condition: Condition| {
+            !excluded.contains(&condition)
+                && probe
+                    .checks()
+                    .any(|(other, check)| other == condition && !check.passing)
+        };
+        // Conditions take priority over the plain failing state, in the configured order, but
+        // only if the probe fails, since an override can pass it despite a failing condition
+        let condition = self.codes.iter().find_map(|(state, code)| match state {
+            ResponseState::Failing(condition) if !passing && failing(*condition) => Some(*code),
+            _ => None,
+        });
+        let state = self.codes.iter().find_map(|(state, code)| match state {
+            ResponseState::Pass if passing => Some(*code),
+            ResponseState::Fail if !passing => Some(*code),
+            _ => None,
+        });
+        match (condition, state) {
+            (Some(code), _) | (None, Some(code)) => code,
+            (None, None) if passing => StatusCode::OK,
+            (None, None) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl FromStr for ResponseCodes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let codes: Result<Arc<[_]>, _> = s
+            .split(',')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let error = || Error::ParseResponseCodes(entry.into());
+                let (state, code) = entry.split_once('=').ok_or_else(error)?;
+                let state = match state {
+                    "pass" => ResponseState::Pass,
+                    "fail" => ResponseState::Fail,
+                    condition => ResponseState::Failing(condition.parse()?),
+                };
+                let code = code.parse().map_err(|_| error())?;
+                Ok((state, code))
+            })
+            .collect();
+        Ok(ResponseCodes {
+            codes: codes?,
+            source: s.into(),
+        })
+    }
+}
+
+impl Valuable for ResponseCodes {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(&self.source)
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::config::Configuration;
+
+    fn query(parameters: &[(&str, &str)]) -> Result<ProbeQuery, String> {
+        ProbeQuery::parse(
+            parameters
+                .iter()
+                .map(|(key, value)| ((*key).into(), (*value).into()))
+                .collect(),
+        )
+    }
+
+    fn codes(source: &str) -> ResponseCodes {
+        source.parse().expect("valid response codes")
+    }
+
+    fn set(probe: &mut Probe, passing: bool, condition: Condition) {
+        probe.apply(status::ChangeOperation::Set(
+            passing,
+            status::Reason {
+                condition,
+                message: String::new(),
+            },
+        ));
+    }
+
+    #[test]
+    fn response_codes_parse() {
+        assert!("".parse::<ResponseCodes>().is_ok());
+        assert!("pass=204,fail=500,watchdog=429"
+            .parse::<ResponseCodes>()
+            .is_ok());
+        assert!("pass".parse::<ResponseCodes>().is_err());
+        assert!("pass=ok".parse::<ResponseCodes>().is_err());
+        assert!("pass=1000".parse::<ResponseCodes>().is_err());
+        assert!("unknown=500".parse::<ResponseCodes>().is_err());
+    }
+
+    #[test]
+    fn response_codes_resolve() {
+        let config = Configuration::defaults().expect("default configuration");
+        let mut probe = status::Status::from_config(&config).readyz;
+        set(&mut probe, true, Condition::Startup);
+        let codes = codes("pass=204,fail=500,watchdog=429,errno=418");
+        assert_eq!(codes.resolve(&probe, &[]), StatusCode::NO_CONTENT);
+
+        set(&mut probe, false, Condition::ErrorNumber);
+        set(&mut probe, false, Condition::Watchdog);
+        assert_eq!(codes.resolve(&probe, &[]), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            codes.resolve(&probe, &[Condition::Watchdog]),
+            StatusCode::IM_A_TEAPOT
+        );
+        assert_eq!(
+            codes.resolve(&probe, &[Condition::Watchdog, Condition::ErrorNumber]),
+            StatusCode::NO_CONTENT
+        );
+
+        set(&mut probe, false, Condition::Stopping);
+        assert_eq!(
+            codes.resolve(&probe, &[Condition::Watchdog, Condition::ErrorNumber]),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            ResponseCodes::from_str("")
+                .expect("empty response codes")
+                .resolve(&probe, &[]),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn response_codes_ignore_conditions_of_passing_probes() {
+        let config = Configuration::defaults().expect("default configuration");
+        let mut probe = status::Status::from_config(&config).readyz;
+        set(&mut probe, false, Condition::Watchdog);
+        set(&mut probe, true, Condition::Override);
+        assert!(probe.value());
+        assert_eq!(
+            codes("pass=200,watchdog=500").resolve(&probe, &[]),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn timeout_is_a_time_span() {
+        let timeout = |value| query(&[("timeout", value)]).map(|query| query.timeout);
+        assert_eq!(timeout("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(timeout("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(timeout("1min 30s"), Ok(Duration::from_secs(90)));
+        assert_eq!(timeout("infinity"), Ok(Duration::MAX));
+        assert!(timeout("soon").is_err());
+        assert_eq!(
+            query(&[]).map(|query| query.timeout),
+            Ok(DEFAULT_WAIT_TIMEOUT)
+        );
+    }
+
+    #[tokio::test]
+    async fn livez_reports_restarts() {
+        let config = Configuration::defaults().expect("default configuration");
+        let status = Arc::new(RwLock::new(status::Status::from_config(&config)));
+        let service = SharedService::default();
+        let (update_sender, _) = broadcast::channel(16);
+        // Like the supervisor after restarting the service
+        service.write().await.restarts = 2;
+
+        let response = livez(
+            Extension(status),
+            Extension(update_sender),
+            Extension(CancellationToken::new()),
+            Extension(Arc::new(RwLock::new(config))),
+            Extension(service),
+            HeaderMap::new(),
+            Query(Vec::new()),
+        )
+        .await;
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("body")
+            .to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).expect("JSON body");
+        assert_eq!(body["restarts"], 2);
+        assert_eq!(body["livez"], false);
+    }
 }
@@ -1,24 +1,46 @@
-use std::{net::Shutdown, os::fd::AsFd, path::PathBuf};
+use std::{
+    io::{self, IoSliceMut},
+    net::Shutdown,
+    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::PathBuf,
+};
 
-use nix::sys::{self, socket::sockopt::RcvBuf};
-use tokio::{net::UnixDatagram, sync::mpsc::Sender};
+use nix::{
+    cmsg_space,
+    sys::{
+        self,
+        socket::{
+            sockopt::{PassCred, RcvBuf},
+            ControlMessageOwned, MsgFlags, UnixCredentials,
+        },
+    },
+};
+use tokio::{
+    io::Interest,
+    net::UnixDatagram,
+    sync::{broadcast, mpsc::Sender},
+};
 use tokio_util::sync::CancellationToken;
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
 use crate::{
     config::{ConfigurationChange, SharedConfiguration},
     error::Error,
     event::Event,
-    message::Message,
+    message::{Message, NotifyAccess},
+    service::SharedService,
+    status::Update,
 };
 
 #[instrument(name = "UDS server", skip_all)]
 pub async fn server(
     token: CancellationToken,
     config: SharedConfiguration,
+    service: SharedService,
     ready_sender: Sender<()>,
     config_sender: Sender<ConfigurationChange>,
     event_sender: Sender<Event>,
+    update_sender: broadcast::Sender<Update>,
 ) -> Result<(), Error> {
     let notify_socket = PathBuf::from(config.read().await.notify_socket.clone());
 
@@ -29,7 +51,11 @@ pub async fn server(
     let fd = socket.as_fd();
 
     let buffer_size = sys::socket::getsockopt(&fd, RcvBuf).map_err(Error::UdsGetSocketOption)?;
+    // The kernel attaches the process of the sender to every datagram, for `main` and `exec`
+    sys::socket::setsockopt(&fd, PassCred, &true).map_err(Error::UdsSetSocketOption)?;
     let mut buffer = vec![0u8; buffer_size];
+    // The kernel limit of descriptors in a single message
+    let mut control = cmsg_space!([RawFd; 253], UnixCredentials);
 
     info!("UDS server ready");
     ready_sender
@@ -38,19 +64,37 @@ pub async fn server(
         .map_err(Error::ReadyChannelSend)?;
 
     loop {
-        let (length, _) = tokio::select! {
+        let (length, descriptors, sender) = tokio::select! {
             () = token.cancelled() => break,
-            result = socket.recv_from(&mut buffer) => result,
+            result = socket.async_io(Interest::READABLE, || {
+                receive(&socket, &mut buffer, &mut control)
+            }) => result,
         }
         .map_err(Error::UdsReceiveDatagram)?;
         let datagram = std::str::from_utf8(&buffer[..length]).map_err(Error::UdsDecodeDatagram)?;
+        let access = config.read().await.unit_notify_access;
+        let main_pid = service.read().await.main_pid;
+        if !permitted(access, sender, main_pid, datagram) {
+            warn!(
+                sender,
+                main_pid,
+                "Ignoring notification, because ADAPTER_UNIT_NOTIFY_ACCESS is {}",
+                access.name()
+            );
+            continue;
+        }
         process_datagram(
             config.clone(),
+            service.clone(),
             config_sender.clone(),
             event_sender.clone(),
+            update_sender.clone(),
             datagram,
+            sender,
         )
         .await?;
+        // Closing the descriptors of `BARRIER=1` signals that all previous messages were processed
+        drop(descriptors);
     }
 
     info!("Shutting down UDS server");
@@ -62,11 +106,71 @@ pub async fn server(
     Ok(())
 }
 
-async fn process_datagram(
+/// Whether `ADAPTER_UNIT_NOTIFY_ACCESS` lets a process notify the adapter, where `main` and `exec`
+/// only accept the main process, since the adapter starts no other processes of the service
+fn permitted(
+    access: NotifyAccess,
+    sender: Option<i32>,
+    main_pid: Option<i32>,
+    datagram: &str,
+) -> bool {
+    match access {
+        NotifyAccess::None => false,
+        NotifyAccess::All => true,
+        NotifyAccess::Main | NotifyAccess::Exec => {
+            // A process in another PID namespace is reported as 0
+            let Some(sender) = sender.filter(|pid| *pid > 0) else {
+                return false;
+            };
+            match main_pid {
+                Some(main_pid) => sender == main_pid,
+                // Until the main PID is known, a process may name itself as the main process
+                None => datagram
+                    .lines()
+                    .any(|line| line.strip_prefix("MAINPID=") == Some(&sender.to_string())),
+            }
+        }
+    }
+}
+
+/// Receives a datagram along with the file descriptors sent with it and the process of its sender
+fn receive(
+    socket: &UnixDatagram,
+    buffer: &mut [u8],
+    control: &mut Vec<u8>,
+) -> io::Result<(usize, Vec<OwnedFd>, Option<i32>)> {
+    let mut iov = [IoSliceMut::new(buffer)];
+    let message = sys::socket::recvmsg::<()>(
+        socket.as_raw_fd(),
+        &mut iov,
+        Some(control),
+        MsgFlags::MSG_DONTWAIT | MsgFlags::MSG_CMSG_CLOEXEC,
+    )?;
+    let mut sender = None;
+    let descriptors = message
+        .cmsgs()
+        .flat_map(|message| match message {
+            ControlMessageOwned::ScmRights(descriptors) => descriptors,
+            ControlMessageOwned::ScmCredentials(credentials) => {
+                sender = Some(credentials.pid());
+                Vec::new()
+            }
+            _ => Vec::new(),
+        })
+        // SAFETY: The kernel has just installed these descriptors for this process alone
+        .map(|descriptor| unsafe { OwnedFd::from_raw_fd(descriptor) })
+        .collect();
+    Ok((message.bytes, descriptors, sender))
+}
+
+pub async fn process_datagram(
     config: SharedConfiguration,
+    service: SharedService,
     config_sender: Sender<ConfigurationChange>,
     event_sender: Sender<Event>,
+    update_sender: broadcast::Sender<Update>,
     datagram: &str,
+    sender: Option<i32>,
 ) -> Result<(), Error> {
     let messages = datagram
         .lines()
@@ -76,6 +180,7 @@ async fn process_datagram(
         if config.read().await.echo {
             println!("{}", String::from(message.clone()));
         }
+        service.write().await.record(&message, sender);
 
         macro_rules! send_event (($e: expr) => {event_sender.send($e).await.map_err(Error::EventChannelSend)};);
         macro_rules! send_config_change (($e: expr) => {config_sender.send($e).await.map_err(Error::ConfigChannelSend)};);
@@ -88,6 +193,13 @@ async fn process_datagram(
             Message::BusError(_) => send_event!(Event::BusError)?,
             Message::Watchdog => send_event!(Event::Watchdog)?,
             Message::WatchdogTrigger => send_event!(Event::WatchdogTrigger)?,
+            Message::Status(text) => {
+                // Having no subscribers is not an error
+                let _ = update_sender.send(Update::Text(text));
+            }
+            Message::NotifyAccess(access) => {
+                send_config_change!(ConfigurationChange::NotifyAccess(access))?;
+            }
             Message::WatchdogMicrosecond(timeout) => {
                 send_config_change!(ConfigurationChange::WatchdogTimeout(timeout))?;
             }
@@ -102,3 +214,34 @@ async fn process_datagram(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_access_only_permits_the_main_process() {
+        for access in [NotifyAccess::Main, NotifyAccess::Exec] {
+            assert!(permitted(access, Some(42), Some(42), "READY=1"));
+            assert!(!permitted(access, Some(43), Some(42), "READY=1"));
+            assert!(!permitted(access, Some(0), Some(0), "READY=1"));
+            assert!(!permitted(access, None, Some(42), "READY=1"));
+            // The main process may name itself while there is none
+            assert!(permitted(access, Some(42), None, "MAINPID=42\nREADY=1"));
+            assert!(!permitted(access, Some(43), None, "MAINPID=42\nREADY=1"));
+            assert!(!permitted(access, Some(42), None, "READY=1"));
+        }
+    }
+
+    #[test]
+    fn all_and_none_ignore_the_sender() {
+        assert!(permitted(NotifyAccess::All, None, Some(42), "READY=1"));
+        assert!(permitted(NotifyAccess::All, Some(43), Some(42), "READY=1"));
+        assert!(!permitted(
+            NotifyAccess::None,
+            Some(42),
+            Some(42),
+            "READY=1"
+        ));
+    }
+}
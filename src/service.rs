@@ -0,0 +1,126 @@
+use std::{collections::BTreeMap, fs, os::unix::fs::MetadataExt, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use nix::{
+    errno::Errno,
+    sys::signal,
+    unistd::{self, Pid},
+};
+use tokio::{sync::RwLock, time::Instant};
+
+use crate::message::Message;
+
+/// What the adapter has learned about the service from its messages and timers
+#[derive(Default)]
+pub struct Service {
+    pub main_pid: Option<i32>,
+    /// Whether the main PID names a process in the PID namespace of the adapter, which is only
+    /// known for the supervised service and for `MAINPID=` sent from the same namespace
+    pub main_pid_trusted: bool,
+    pub status: Option<String>,
+    pub error_number: Option<i32>,
+    pub bus_error: Option<String>,
+    pub exit_status: Option<i32>,
+    /// Number of times the supervised service has restarted
+    pub restarts: u32,
+    /// Number of processed messages by key
+    pub messages: BTreeMap<&'static str, u64>,
+    pub last_message: Option<Instant>,
+    pub watchdog_ping: Option<DateTime<Utc>>,
+    pub watchdog_deadline: Option<DateTime<Utc>>,
+    pub startup_deadline: Option<DateTime<Utc>>,
+}
+
+impl Service {
+    /// Records a message, along with the process that has sent it if it is known
+    pub fn record(&mut self, message: &Message, sender: Option<i32>) {
+        *self.messages.entry(message.key()).or_default() += 1;
+        self.last_message = Some(Instant::now());
+        match message {
+            Message::MainPID(pid) => {
+                self.main_pid = Some(*pid);
+                self.main_pid_trusted = sender.is_some_and(same_pid_namespace);
+            }
+            Message::Status(status) => self.status = Some(status.clone()),
+            Message::ErrorNumber(number) => self.error_number = Some(*number),
+            Message::BusError(error) => self.bus_error = Some(error.clone()),
+            Message::ExitStatus(status) => self.exit_status = Some(*status),
+            _ => {}
+        }
+    }
+
+    /// The main process, unless it is unknown or a main PID from another PID namespace, which can
+    /// name any process, even the adapter itself
+    pub fn main_process(&self) -> Option<Pid> {
+        self.main_pid
+            .filter(|pid| self.main_pid_trusted && *pid > 0)
+            .map(Pid::from_raw)
+            .filter(|pid| *pid != unistd::getpid())
+    }
+
+    /// Whether the main process is still running, if it is known from [`Self::main_process`]
+    pub fn main_process_alive(&self) -> Option<bool> {
+        self.main_process()
+            .map(|pid| signal::kill(pid, None) != Err(Errno::ESRCH))
+    }
+}
+
+/// Whether a process is in the PID namespace of the adapter, where a process in another one is
+/// reported as 0 and one that cannot be inspected is not trusted
+fn same_pid_namespace(pid: i32) -> bool {
+    let namespace =
+        |path: &str| fs::metadata(path).map(|metadata| (metadata.dev(), metadata.ino()));
+    pid > 0
+        && namespace("/proc/self/ns/pid").is_ok_and(|own| {
+            namespace(&format!("/proc/{pid}/ns/pid")).is_ok_and(|other| own == other)
+        })
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub type SharedService = Arc<RwLock<Service>>;
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn main_pid_is_trusted_from_the_same_namespace() {
+        let own = i32::try_from(process::id()).expect("PID");
+        let mut service = Service::default();
+        service.record(&Message::MainPID(1), Some(own));
+        assert_eq!(service.main_process(), Some(Pid::from_raw(1)));
+        // Another namespace, or an HTTP request
+        for sender in [Some(0), None] {
+            service.record(&Message::MainPID(1), sender);
+            assert_eq!(service.main_process(), None);
+            assert_eq!(service.main_process_alive(), None);
+        }
+    }
+
+    #[test]
+    fn main_process_is_never_the_adapter() {
+        let own = i32::try_from(process::id()).expect("PID");
+        let mut service = Service::default();
+        for pid in [0, -1, own] {
+            service.record(&Message::MainPID(pid), Some(own));
+            assert_eq!(service.main_process(), None);
+        }
+    }
+
+    #[test]
+    fn main_process_alive_detects_exit() {
+        let mut child = process::Command::new("true").spawn().expect("child");
+        let pid = i32::try_from(child.id()).expect("PID");
+        let mut service = Service {
+            main_pid: Some(pid),
+            main_pid_trusted: true,
+            ..Service::default()
+        };
+        child.wait().expect("exit status");
+        assert_eq!(service.main_process_alive(), Some(false));
+        service.main_pid = Some(1);
+        assert_eq!(service.main_process_alive(), Some(true));
+    }
+}
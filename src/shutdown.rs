@@ -0,0 +1,139 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use tokio::{
+    sync::{broadcast, mpsc::Sender},
+    time,
+};
+use tracing::{info, warn};
+use valuable::Valuable;
+
+use crate::{
+    config::SharedConfiguration,
+    error::Error,
+    event::Event,
+    service::SharedService,
+    status::{Change, ChangeOperation, Condition, Reason, Update},
+};
+
+/// How often the main process is checked while waiting for it to exit
+pub const EXIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the adapter waits for after draining, before it shuts down
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownWait {
+    None,
+    Stopping,
+    Exit,
+}
+
+impl ShutdownWait {
+    fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Stopping => "stopping",
+            Self::Exit => "exit",
+        }
+    }
+}
+
+impl fmt::Display for ShutdownWait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Valuable for ShutdownWait {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(self.name())
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+impl FromStr for ShutdownWait {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "stopping" => Ok(Self::Stopping),
+            "exit" => Ok(Self::Exit),
+            _ => Err(Error::ParseShutdownWait(s.into())),
+        }
+    }
+}
+
+/// Fails `/readyz` and keeps serving for `ADAPTER_SHUTDOWN_DRAIN_SEC`, so that the `Pod` is taken
+/// out of its endpoints before it stops, and then waits for the service like
+/// `ADAPTER_SHUTDOWN_WAIT`, unless the service is supervised and still has to be stopped
+pub async fn drain(
+    config: SharedConfiguration,
+    service: SharedService,
+    status_sender: Sender<Change>,
+    update_sender: broadcast::Sender<Update>,
+    supervising: bool,
+) -> Result<(), Error> {
+    // Subscribed before draining, so that a `STOPPING=1` sent while draining is not missed
+    let mut updates = update_sender.subscribe();
+
+    let config_lock = config.read().await;
+    let drain_sec = config_lock.shutdown_drain_sec;
+    let wait = config_lock.shutdown_wait;
+    let stop_timeout = config_lock.unit_timeout_stop_sec;
+    drop(config_lock);
+
+    info!("Draining for {}", drain_sec);
+    status_sender
+        .send(Change {
+            healthz: ChangeOperation::Keep,
+            livez: ChangeOperation::Keep,
+            readyz: ChangeOperation::Set(
+                false,
+                Reason {
+                    condition: Condition::Drain,
+                    message: String::from("The adapter is shutting down"),
+                },
+            ),
+        })
+        .await
+        .map_err(Error::StatusChannelSend)?;
+    time::sleep(drain_sec.into()).await;
+
+    // A supervised service is stopped by the adapter, which always waits for it to exit
+    if supervising || wait == ShutdownWait::None {
+        return Ok(());
+    }
+
+    info!("Waiting for the service to stop");
+    let waiting = async {
+        loop {
+            let service_lock = service.read().await;
+            let alive = service_lock.main_process_alive();
+            let unknown = service_lock.main_pid.is_none();
+            drop(service_lock);
+            match alive {
+                Some(false) => break,
+                None if unknown && wait == ShutdownWait::Exit => break,
+                _ => {}
+            }
+            // A main process from another PID namespace cannot be checked, so only `STOPPING=1`
+            // can end the wait
+            let stopping = wait == ShutdownWait::Stopping || alive.is_none();
+            tokio::select! {
+                update = updates.recv(), if stopping => {
+                    if let Ok(Update::Event(Event::Stopping)) = update {
+                        break;
+                    }
+                },
+                () = time::sleep(EXIT_INTERVAL) => {},
+            }
+        }
+    };
+    if time::timeout(stop_timeout.into(), waiting).await.is_err() {
+        warn!("The service did not stop within ADAPTER_UNIT_TIMEOUT_STOP_SEC");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::{fmt, str::FromStr};
+
+use nix::sys::signal::Signal;
+use valuable::Valuable;
+
+use crate::{config::ConfigSignal, error::Error};
+
+/// The signals the adapter handles, along with what it does on each by default
+const DEFAULTS: [(Signal, SignalAction); 8] = [
+    (Signal::SIGHUP, SignalAction::Reload),
+    (Signal::SIGINT, SignalAction::Shutdown),
+    (Signal::SIGQUIT, SignalAction::Shutdown),
+    (Signal::SIGTERM, SignalAction::Drain),
+    (Signal::SIGALRM, SignalAction::Ignore),
+    (Signal::SIGPIPE, SignalAction::Ignore),
+    (Signal::SIGUSR1, SignalAction::Dump),
+    (Signal::SIGUSR2, SignalAction::Ignore),
+];
+
+/// What the adapter does when it receives a signal
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SignalAction {
+    /// Shuts down right away, or stops the supervised service
+    Shutdown,
+    /// Shuts down like `ADAPTER_SHUTDOWN_DRAIN_SEC` and `ADAPTER_SHUTDOWN_WAIT`
+    Drain,
+    /// Reloads the configuration
+    Reload,
+    /// Writes the state served by `/status` to standard error as JSON
+    Dump,
+    /// Reopens `ADAPTER_LOG_FILE`, e.g. after it has been rotated
+    Reopen,
+    Ignore,
+}
+
+impl SignalAction {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Shutdown => "shutdown",
+            Self::Drain => "drain",
+            Self::Reload => "reload",
+            Self::Dump => "dump",
+            Self::Reopen => "reopen",
+            Self::Ignore => "ignore",
+        }
+    }
+}
+
+impl fmt::Display for SignalAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SignalAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shutdown" => Ok(Self::Shutdown),
+            "drain" => Ok(Self::Drain),
+            "reload" => Ok(Self::Reload),
+            "dump" => Ok(Self::Dump),
+            "reopen" => Ok(Self::Reopen),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err(Error::ParseSignalActions(s.into())),
+        }
+    }
+}
+
+/// The action of each handled signal, e.g. `SIGUSR2=reopen,SIGALRM=shutdown`, where signals that
+/// are not listed keep their default action
+#[derive(Clone)]
+pub struct SignalActions {
+    actions: [(Signal, SignalAction); 8],
+    source: String,
+}
+
+impl SignalActions {
+    pub fn action(&self, signal: Signal) -> SignalAction {
+        self.actions
+            .iter()
+            .find(|(handled, _)| *handled == signal)
+            .map_or(SignalAction::Shutdown, |(_, action)| *action)
+    }
+}
+
+impl FromStr for SignalActions {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut actions = DEFAULTS;
+        for entry in s.split(',').filter(|entry| !entry.is_empty()) {
+            let error = || Error::ParseSignalActions(entry.into());
+            let (signal, action) = entry.split_once('=').ok_or_else(error)?;
+            let ConfigSignal(signal) = signal.trim().parse().map_err(|_| error())?;
+            let action = action.trim().parse().map_err(|_| error())?;
+            let (_, current) = actions
+                .iter_mut()
+                .find(|(handled, _)| *handled == signal)
+                .ok_or_else(error)?;
+            *current = action;
+        }
+        // Written out in full, so that the configuration shows every action
+        let source = actions
+            .iter()
+            .map(|(signal, action)| format!("{}={action}", signal.as_str()))
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(SignalActions { actions, source })
+    }
+}
+
+impl Valuable for SignalActions {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(&self.source)
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_actions_default() {
+        let actions: SignalActions = "".parse().expect("default signal actions");
+        for (signal, action) in DEFAULTS {
+            assert!(actions.action(signal) == action, "{signal}");
+        }
+        assert_eq!(
+            actions.source,
+            "SIGHUP=reload,SIGINT=shutdown,SIGQUIT=shutdown,SIGTERM=drain,SIGALRM=ignore,\
+             SIGPIPE=ignore,SIGUSR1=dump,SIGUSR2=ignore"
+        );
+    }
+
+    #[test]
+    fn signal_actions_merge_over_defaults() {
+        let actions: SignalActions = "SIGUSR2=reopen, ALRM = shutdown,15=shutdown"
+            .parse()
+            .expect("valid signal actions");
+        assert!(actions.action(Signal::SIGUSR2) == SignalAction::Reopen);
+        assert!(actions.action(Signal::SIGALRM) == SignalAction::Shutdown);
+        assert!(actions.action(Signal::SIGTERM) == SignalAction::Shutdown);
+        assert!(actions.action(Signal::SIGHUP) == SignalAction::Reload);
+    }
+
+    #[test]
+    fn signal_actions_reject_invalid_input() {
+        for invalid in [
+            "SIGUSR2",
+            "SIGUSR2=",
+            "SIGUSR2=restart",
+            "SIGNOPE=ignore",
+            "SIGKILL=ignore",
+            "SIGCHLD=dump",
+        ] {
+            assert!(invalid.parse::<SignalActions>().is_err(), "{invalid:?}");
+        }
+    }
+}
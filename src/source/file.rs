@@ -0,0 +1,58 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use toml::{Spanned, Value};
+
+use crate::error::Error;
+
+/// A value of a configuration file, in the format of its environment variable
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+/// Reads the entries of a TOML configuration file
+pub fn read(path: &Path) -> Result<Vec<Entry>, Error> {
+    let contents =
+        fs::read_to_string(path).map_err(|error| Error::ConfigFileRead(path.into(), error))?;
+    let table: BTreeMap<String, Spanned<Value>> =
+        toml::from_str(&contents).map_err(|error| Error::ConfigFileParse(path.into(), error))?;
+
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let line = contents[..value.span().start].lines().count().max(1);
+            let value = flatten(value.into_inner())
+                .ok_or_else(|| Error::ConfigFileValue(path.into(), line, key.clone()))?;
+            Ok(Entry { key, value, line })
+        })
+        .collect()
+}
+
+/// Writes a value like its environment variable: lists are comma-separated and tables are
+/// comma-separated `key=value` pairs in the order of the file
+fn flatten(value: Value) -> Option<String> {
+    match value {
+        Value::Array(values) => values
+            .into_iter()
+            .map(scalar)
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(",")),
+        Value::Table(table) => table
+            .into_iter()
+            .map(|(key, value)| scalar(value).map(|value| format!("{key}={value}")))
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(",")),
+        value => scalar(value),
+    }
+}
+
+fn scalar(value: Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        Value::Boolean(value) => Some(value.to_string()),
+        Value::Datetime(_) | Value::Array(_) | Value::Table(_) => None,
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Seconds, error::Error};
+
+/// A value of a unit file, in the format of the environment variable of its configuration key
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub path: PathBuf,
+    pub line: usize,
+    pub directive: String,
+}
+
+/// An assignment in the `[Unit]` or `[Service]` section of a unit file or one of its drop-ins
+struct Directive {
+    section: String,
+    key: String,
+    value: String,
+    path: PathBuf,
+    line: usize,
+}
+
+/// Reads the entries of a `systemd` service unit file and the `.conf` drop-ins in the
+/// `<unit>.d` directory next to it, along with warnings about directives that are not supported
+pub fn read(path: &Path) -> Result<(Vec<Entry>, Vec<String>), Error> {
+    let mut directives = Vec::new();
+    let mut warnings = Vec::new();
+    parse(path, &mut directives, &mut warnings)?;
+
+    let mut directory = OsString::from(path);
+    directory.push(".d");
+    let drop_ins = match fs::read_dir(&directory) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| Error::UnitFileRead(directory.clone().into(), error))?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => return Err(Error::UnitFileRead(directory.into(), error)),
+    };
+    let mut drop_ins: Vec<_> = drop_ins
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "conf")
+        })
+        .collect();
+    // Drop-ins are applied in the order of their file names
+    drop_ins.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    for drop_in in drop_ins {
+        parse(&drop_in, &mut directives, &mut warnings)?;
+    }
+
+    let mut entries = Vec::new();
+    let mut started = None;
+    for directive in directives {
+        let mut entry = |key: &str, value: String| {
+            entries.push(Entry {
+                key: key.into(),
+                value,
+                path: directive.path.clone(),
+                line: directive.line,
+                directive: directive.key.clone(),
+            });
+        };
+        match (directive.section.as_str(), directive.key.as_str()) {
+            ("Unit", "StartLimitBurst") => {
+                entry("unit_start_limit_burst", directive.value.clone());
+            }
+            ("Unit", "StartLimitIntervalSec") => {
+                entry("unit_start_limit_interval_sec", directive.value.clone());
+            }
+            // The other settings of the unit, like its description and dependencies, have no
+            // meaning in a container
+            ("Unit", _) => {}
+            (_, "TimeoutStartSec") => entry("unit_timeout_start_sec", timeout(&directive.value)),
+            (_, "TimeoutStopSec") => entry("unit_timeout_stop_sec", timeout(&directive.value)),
+            (_, "TimeoutSec") => {
+                entry("unit_timeout_start_sec", timeout(&directive.value));
+                entry("unit_timeout_stop_sec", timeout(&directive.value));
+            }
+            (_, "WatchdogSec") => entry("unit_watchdog_sec", directive.value.clone()),
+            (_, "WatchdogSignal") => entry("unit_watchdog_signal", directive.value.clone()),
+            (_, "TimeoutAbortSec") => entry("unit_timeout_abort_sec", directive.value.clone()),
+            (_, "RuntimeMaxSec") => entry("unit_runtime_max_sec", directive.value.clone()),
+            (_, "NotifyAccess") => entry("unit_notify_access", directive.value.clone()),
+            (_, "Restart") => entry("unit_restart", directive.value.clone()),
+            (_, "RestartSec") => entry("unit_restart_sec", directive.value.clone()),
+            (_, "Type") => match directive.value.as_str() {
+                "notify" | "notify-reload" => {}
+                "simple" | "exec" | "idle" => started = Some(directive),
+                _ => warnings.push(format!(
+                    "Unsupported service type in unit file {}:{}: {}",
+                    directive.path.display(),
+                    directive.line,
+                    directive.value
+                )),
+            },
+            _ => warnings.push(format!(
+                "Unsupported directive in unit file {}:{}: {}=",
+                directive.path.display(),
+                directive.line,
+                directive.key
+            )),
+        }
+    }
+
+    // Services that do not send `READY=1` are started as soon as they run, whatever the timeout
+    if let Some(directive) = started {
+        for (key, value) in [
+            ("initial_livez", "true"),
+            ("initial_readyz", "true"),
+            ("unit_timeout_start_sec", "infinity"),
+        ] {
+            entries.push(Entry {
+                key: key.into(),
+                value: value.into(),
+                path: directive.path.clone(),
+                line: directive.line,
+                directive: directive.key.clone(),
+            });
+        }
+    }
+
+    Ok((entries, warnings))
+}
+
+/// Adds the directives of the `[Unit]` and `[Service]` sections of a file, where later
+/// assignments replace earlier ones and empty assignments reset them
+fn parse(
+    path: &Path,
+    directives: &mut Vec<Directive>,
+    warnings: &mut Vec<String>,
+) -> Result<(), Error> {
+    let contents =
+        fs::read_to_string(path).map_err(|error| Error::UnitFileRead(path.into(), error))?;
+
+    let mut section = None;
+    let mut assignment = String::new();
+    let mut start = 0;
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if assignment.is_empty() {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = ["Unit", "Service"]
+                    .contains(&name)
+                    .then(|| String::from(name));
+                continue;
+            }
+            start = index + 1;
+        }
+        if let Some(line) = line.strip_suffix('\\') {
+            assignment.push_str(line);
+            assignment.push(' ');
+            continue;
+        }
+        assignment.push_str(line);
+        let assignment = std::mem::take(&mut assignment);
+        let Some(section) = &section else {
+            continue;
+        };
+
+        let Some((key, value)) = assignment.split_once('=') else {
+            warnings.push(format!(
+                "Could not parse line in unit file {}:{start}",
+                path.display()
+            ));
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        directives.retain(|directive| directive.section != *section || directive.key != key);
+        if !value.is_empty() {
+            directives.push(Directive {
+                section: section.clone(),
+                key: key.into(),
+                value: value.into(),
+                path: path.into(),
+                line: start,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Like `systemd`, a timeout of 0 disables it
+fn timeout(value: &str) -> String {
+    match value.parse() {
+        Ok(Seconds::ZERO) => String::from("infinity"),
+        _ => value.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Writes files into a new directory, and returns it along with the path of the first file
+    fn write(name: &str, files: &[(&str, &str)]) -> (TempDir, PathBuf) {
+        let directory = TempDir::new(&format!("unit-{name}"));
+        for (file, contents) in files {
+            let path = directory.join(file);
+            fs::create_dir_all(path.parent().expect("file in directory")).expect("directory");
+            fs::write(path, contents).expect("file");
+        }
+        let path = directory.join(files[0].0);
+        (directory, path)
+    }
+
+    fn entries(path: &Path) -> (Vec<(String, String)>, Vec<String>) {
+        let (entries, warnings) = read(path).expect("readable unit file");
+        let entries = entries
+            .into_iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect();
+        (entries, warnings)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).into(), (*value).into()))
+            .collect()
+    }
+
+    #[test]
+    fn directives_map_to_keys() {
+        let (_directory, path) = write(
+            "directives",
+            &[(
+                "app.service",
+                "[Unit]\n\
+                 Description=Ignored\n\
+                 StartLimitBurst=3\n\
+                 \n\
+                 # A comment\n\
+                 ; Another comment\n\
+                 [Service]\n\
+                 Type=notify\n\
+                 WatchdogSec=10\n\
+                 TimeoutSec=1min\\\n\
+                 30s\n\
+                 Restart=always\n\
+                 RestartSec=5\n\
+                 RestartSec=\n\
+                 NotifyAccess=main\n\
+                 [Install]\n\
+                 WantedBy=multi-user.target\n",
+            )],
+        );
+        let (entries, warnings) = entries(&path);
+        assert_eq!(
+            entries,
+            pairs(&[
+                ("unit_start_limit_burst", "3"),
+                ("unit_watchdog_sec", "10"),
+                ("unit_timeout_start_sec", "1min 30s"),
+                ("unit_timeout_stop_sec", "1min 30s"),
+                ("unit_restart", "always"),
+                ("unit_notify_access", "main"),
+            ])
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    #[test]
+    fn zero_timeouts_are_infinite() {
+        let (_directory, path) = write(
+            "timeouts",
+            &[(
+                "app.service",
+                "[Service]\nTimeoutStartSec=0\nTimeoutStopSec=0s\n",
+            )],
+        );
+        assert_eq!(
+            entries(&path).0,
+            pairs(&[
+                ("unit_timeout_start_sec", "infinity"),
+                ("unit_timeout_stop_sec", "infinity"),
+            ])
+        );
+    }
+
+    #[test]
+    fn drop_ins_apply_in_order() {
+        let (_directory, path) = write(
+            "drop-ins",
+            &[
+                ("app.service", "[Service]\nWatchdogSec=10\nRestartSec=1\n"),
+                ("app.service.d/20-late.conf", "[Service]\nWatchdogSec=30\n"),
+                (
+                    "app.service.d/10-early.conf",
+                    "[Service]\nWatchdogSec=20\nRestartSec=\n",
+                ),
+                ("app.service.d/ignored.txt", "[Service]\nWatchdogSec=40\n"),
+            ],
+        );
+        assert_eq!(entries(&path).0, pairs(&[("unit_watchdog_sec", "30")]));
+    }
+
+    #[test]
+    fn simple_services_start_ready() {
+        let (_directory, path) = write("simple", &[("app.service", "[Service]\nType=simple\n")]);
+        assert_eq!(
+            entries(&path).0,
+            pairs(&[
+                ("initial_livez", "true"),
+                ("initial_readyz", "true"),
+                ("unit_timeout_start_sec", "infinity"),
+            ])
+        );
+    }
+
+    #[test]
+    fn unsupported_directives_warn() {
+        let (_directory, path) = write(
+            "unsupported",
+            &[(
+                "app.service",
+                "[Service]\nType=forking\nExecStart=/bin/app\nnot an assignment\n",
+            )],
+        );
+        let (entries, warnings) = entries(&path);
+        assert!(entries.is_empty());
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].contains("Could not parse line"));
+        assert!(warnings[1].contains("Unsupported service type"));
+        assert!(warnings[2].contains("ExecStart="));
+    }
+
+    #[test]
+    fn missing_file_fails() {
+        assert!(read(Path::new("/nonexistent/app.service")).is_err());
+    }
+}
@@ -1,28 +1,193 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Arc};
 
 use tokio::sync::{
+    broadcast,
     mpsc::{Receiver, Sender},
     RwLock,
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{info, instrument};
+use valuable::Valuable;
 
-use crate::{config::Configuration, error::Error};
+use crate::{config::Configuration, error::Error, event::Event};
 
+#[derive(Clone, PartialEq, Eq)]
 pub struct Status {
-    pub healthz: bool,
-    pub livez: bool,
-    pub readyz: bool,
+    pub healthz: Probe,
+    pub livez: Probe,
+    pub readyz: Probe,
 }
 
 impl Status {
     pub fn from_config(config: &Configuration) -> Self {
         Status {
-            healthz: false,
-            livez: config.initial_livez,
-            readyz: config.initial_readyz,
+            healthz: Probe::new(Condition::Adapter, false, "The adapter is starting"),
+            livez: Probe::new(
+                Condition::Initial,
+                config.initial_livez,
+                format!("ADAPTER_INITIAL_LIVEZ is {}", config.initial_livez),
+            ),
+            readyz: Probe::new(
+                Condition::Initial,
+                config.initial_readyz,
+                format!("ADAPTER_INITIAL_READYZ is {}", config.initial_readyz),
+            ),
         }
     }
+
+    /// Whether all probe values are equal, ignoring the reasons behind them
+    fn same_values(&self, other: &Status) -> bool {
+        self.healthz.value() == other.healthz.value()
+            && self.livez.value() == other.livez.value()
+            && self.readyz.value() == other.readyz.value()
+    }
+}
+
+/// The conditions contributing to a probe, which passes if all of its conditions pass
+#[derive(Clone, PartialEq, Eq)]
+pub struct Probe(BTreeMap<Condition, Check>);
+
+impl Probe {
+    fn new(condition: Condition, passing: bool, reason: impl Into<String>) -> Self {
+        Probe(BTreeMap::from([(
+            condition,
+            Check {
+                passing,
+                reason: reason.into(),
+            },
+        )]))
+    }
+
+    pub fn value(&self) -> bool {
+        self.value_excluding(&[])
+    }
+
+    pub fn value_excluding(&self, excluded: &[Condition]) -> bool {
+        if let Some(check) = self.0.get(&Condition::Override) {
+            if !excluded.contains(&Condition::Override) {
+                return check.passing;
+            }
+        }
+        self.0.iter().all(|(condition, check)| {
+            check.passing || excluded.contains(condition) || *condition == Condition::Override
+        })
+    }
+
+    pub fn checks(&self) -> impl Iterator<Item = (Condition, &Check)> {
+        self.0.iter().map(|(condition, check)| (*condition, check))
+    }
+
+    pub fn apply(&mut self, operation: ChangeOperation) {
+        match operation {
+            ChangeOperation::Keep => {}
+            // A passing change clears every failing condition, so the last change decides the value
+            ChangeOperation::Set(true, reason) if !reason.condition.owned() => {
+                for (condition, check) in &mut self.0 {
+                    if !condition.owned() && !check.passing {
+                        check.passing = true;
+                        check.reason = format!("Cleared by {}", reason.condition);
+                    }
+                }
+                self.0.insert(
+                    reason.condition,
+                    Check {
+                        passing: true,
+                        reason: reason.message,
+                    },
+                );
+            }
+            ChangeOperation::Set(passing, reason) => {
+                self.0.insert(
+                    reason.condition,
+                    Check {
+                        passing,
+                        reason: reason.message,
+                    },
+                );
+            }
+            ChangeOperation::Remove(condition) => {
+                self.0.remove(&condition);
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Check {
+    pub passing: bool,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Valuable)]
+pub enum Condition {
+    /// The adapter itself is ready to receive messages
+    Adapter,
+    /// The value configured before any event is received
+    Initial,
+    /// The service has finished starting up
+    Startup,
+    /// The service is reloading or stopping
+    Stopping,
+    /// The service has reported an error number
+    ErrorNumber,
+    /// The service has reported a D-Bus error
+    BusError,
+    /// The service is keeping the watchdog alive
+    Watchdog,
+    /// The supervised service is running
+    Exit,
+    /// The supervised service has restarted without hitting its start limit
+    Restart,
+    /// The adapter is not shutting down
+    Drain,
+    /// An administrator has forced the value of the probe, regardless of the other conditions
+    Override,
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Adapter => "adapter",
+            Self::Initial => "initial",
+            Self::Startup => "startup",
+            Self::Stopping => "stopping",
+            Self::ErrorNumber => "errno",
+            Self::BusError => "buserror",
+            Self::Watchdog => "watchdog",
+            Self::Exit => "exit",
+            Self::Restart => "restart",
+            Self::Drain => "drain",
+            Self::Override => "override",
+        })
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adapter" => Ok(Self::Adapter),
+            "initial" => Ok(Self::Initial),
+            "startup" => Ok(Self::Startup),
+            "stopping" => Ok(Self::Stopping),
+            "errno" => Ok(Self::ErrorNumber),
+            "buserror" => Ok(Self::BusError),
+            "watchdog" => Ok(Self::Watchdog),
+            "exit" => Ok(Self::Exit),
+            "restart" => Ok(Self::Restart),
+            "drain" => Ok(Self::Drain),
+            "override" => Ok(Self::Override),
+            _ => Err(Error::ParseCondition(s.into())),
+        }
+    }
+}
+
+impl Condition {
+    /// Whether only changes of this condition itself can clear it, rather than any passing change
+    fn owned(self) -> bool {
+        matches!(self, Self::Restart | Self::Drain | Self::Override)
+    }
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -34,6 +199,7 @@ pub async fn status_writer(
     status: SharedStatus,
     mut status_receiver: Receiver<Change>,
     ready_sender: Sender<()>,
+    update_sender: broadcast::Sender<Update>,
 ) -> Result<(), Error> {
     info!("Status writer ready");
     ready_sender
@@ -48,13 +214,14 @@ pub async fn status_writer(
         }
         .ok_or(Error::StatusChannelClosed)?;
         let mut status_lock = status.write().await;
-        macro_rules! apply (($f: ident) => {
-            match change.$f {
-                ChangeOperation::Keep => status_lock.$f,
-                ChangeOperation::Set(value) => value}});
-        status_lock.healthz = apply!(healthz);
-        status_lock.livez = apply!(livez);
-        status_lock.readyz = apply!(readyz);
+        let previous = status_lock.clone();
+        status_lock.healthz.apply(change.healthz);
+        status_lock.livez.apply(change.livez);
+        status_lock.readyz.apply(change.readyz);
+        if !status_lock.same_values(&previous) {
+            // Having no subscribers is not an error
+            let _ = update_sender.send(Update::Status(status_lock.clone()));
+        }
     }
 
     info!("Shutting down status writer");
@@ -70,5 +237,74 @@ pub struct Change {
 
 pub enum ChangeOperation {
     Keep,
-    Set(bool),
+    Set(bool, Reason),
+    Remove(Condition),
+}
+
+/// Why a probe has changed
+#[derive(Clone)]
+pub struct Reason {
+    pub condition: Condition,
+    pub message: String,
+}
+
+/// Published to subscribers whenever the adapter state changes
+#[derive(Clone)]
+pub enum Update {
+    /// A probe has changed its value
+    Status(Status),
+    /// An event has been processed
+    Event(Event),
+    /// The service has sent a `STATUS=` message
+    Text(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(passing: bool, condition: Condition, message: &str) -> ChangeOperation {
+        ChangeOperation::Set(
+            passing,
+            Reason {
+                condition,
+                message: message.into(),
+            },
+        )
+    }
+
+    fn reasons(probe: &Probe) -> Vec<(Condition, bool, &str)> {
+        probe
+            .checks()
+            .map(|(condition, check)| (condition, check.passing, check.reason.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn passing_change_clears_failing_conditions() {
+        let mut probe = Probe::new(Condition::Initial, false, "ADAPTER_INITIAL_READYZ is false");
+        probe.apply(set(false, Condition::ErrorNumber, "Received event errno"));
+        probe.apply(set(true, Condition::Watchdog, "Received event watchdog"));
+        probe.apply(set(true, Condition::Startup, "Received event ready"));
+        assert!(probe.value());
+        assert_eq!(
+            reasons(&probe),
+            [
+                (Condition::Initial, true, "Cleared by watchdog"),
+                (Condition::Startup, true, "Received event ready"),
+                (Condition::ErrorNumber, true, "Cleared by watchdog"),
+                (Condition::Watchdog, true, "Received event watchdog"),
+            ]
+        );
+    }
+
+    #[test]
+    fn passing_change_keeps_owned_conditions() {
+        let mut probe = Probe::new(Condition::Initial, true, "ADAPTER_INITIAL_READYZ is true");
+        probe.apply(set(false, Condition::Drain, "Draining"));
+        probe.apply(set(true, Condition::Startup, "Received event ready"));
+        assert!(!probe.value());
+        probe.apply(ChangeOperation::Remove(Condition::Drain));
+        assert!(probe.value());
+    }
 }
@@ -0,0 +1,569 @@
+use std::{
+    collections::VecDeque,
+    env,
+    ffi::{CStr, CString, OsString},
+    fmt, future, io,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::PathBuf,
+    process,
+    str::FromStr,
+    sync::Arc,
+};
+
+use nix::{
+    errno::Errno,
+    libc,
+    sys::{
+        prctl,
+        signal::{self, Signal},
+        wait::{self, WaitPidFlag, WaitStatus},
+    },
+    unistd::{self, Pid},
+};
+use tokio::{
+    signal::unix::{self, SignalKind},
+    sync::{
+        broadcast,
+        mpsc::{Receiver, Sender},
+        oneshot,
+    },
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+use valuable::Valuable;
+
+use crate::{
+    config::{Configuration, Seconds, SharedConfiguration},
+    error::{Error, EXIT_FAILURE},
+    event::Event,
+    service::SharedService,
+    status::{Change, ChangeOperation, Condition, Reason, Update},
+    timer::watchdog::Message,
+};
+
+/// Variables that `systemd` passes to a service, which are replaced in the adapter's environment
+const VARIABLES: [&CStr; 3] = [c"NOTIFY_SOCKET", c"WATCHDOG_USEC", c"WATCHDOG_PID"];
+
+#[instrument(name = "Supervisor", skip_all)]
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::too_many_lines)]
+pub async fn supervisor(
+    token: CancellationToken,
+    config: SharedConfiguration,
+    service: SharedService,
+    command: Arc<[String]>,
+    start_receiver: oneshot::Receiver<()>,
+    mut signal_receiver: Receiver<Signal>,
+    ready_sender: Sender<()>,
+    watchdog_sender: Sender<Message>,
+    event_sender: Sender<Event>,
+    status_sender: Sender<Change>,
+    update_sender: broadcast::Sender<Update>,
+) -> Result<(), Error> {
+    // Registered before the service starts, so that its exit cannot be missed
+    let mut child = unix::signal(SignalKind::child()).map_err(Error::Signal)?;
+    let mut updates = update_sender.subscribe();
+    // Orphaned descendants of the service are reparented to the adapter rather than to PID 1, so
+    // that `reap` collects them even if the adapter is not PID 1 of the container
+    if let Err(error) = prctl::set_child_subreaper(true) {
+        warn!(%error, "Could not become a subreaper, so orphans of the service are not reaped");
+    }
+
+    info!("Supervisor ready");
+    ready_sender
+        .send(())
+        .await
+        .map_err(Error::ReadyChannelSend)?;
+
+    // The service starts once the notify socket and the probes are ready
+    tokio::select! {
+        () = token.cancelled() => return Ok(()),
+        result = start_receiver => result.map_err(|_| Error::SupervisorStart)?,
+    }
+
+    let mut starts = VecDeque::new();
+    let mut restarts = 0;
+    loop {
+        let pid = spawn(&command, &*config.read().await)?;
+        info!(pid = pid.as_raw(), "Started service");
+        starts.push_back(Instant::now());
+        let mut service_lock = service.write().await;
+        service_lock.main_pid = Some(pid.as_raw());
+        service_lock.main_pid_trusted = true;
+        service_lock.exit_status = None;
+        drop(service_lock);
+        watchdog_sender
+            .send(Message::Start)
+            .await
+            .map_err(Error::WatchdogChannelSend)?;
+
+        let exit = wait(
+            pid,
+            &token,
+            &config,
+            &mut child,
+            &mut signal_receiver,
+            &mut updates,
+        )
+        .await?;
+        let status = exit.termination.status();
+        info!(status, "Service exited");
+        let mut service_lock = service.write().await;
+        service_lock.main_pid = None;
+        service_lock.main_pid_trusted = false;
+        let exit_code = exit.termination.exit_code(service_lock.exit_status);
+        drop(service_lock);
+
+        if exit.cancelled {
+            // The event listener may already have shut down
+            info!("Shutting down supervisor");
+            return Ok(());
+        }
+        event_sender
+            .send(Event::Exit(status))
+            .await
+            .map_err(Error::EventChannelSend)?;
+
+        let config_lock = config.read().await;
+        // The event listener shuts down the adapter with the exit code of the event instead
+        if config_lock.status_shutdown.contains(Event::Exit(status)) {
+            drop(config_lock);
+            token.cancelled().await;
+            info!("Shutting down supervisor");
+            return Ok(());
+        }
+        let restart = config_lock.unit_restart;
+        let restart_sec = config_lock.unit_restart_sec;
+        let burst = config_lock.unit_start_limit_burst;
+        let interval = config_lock.unit_start_limit_interval_sec;
+        drop(config_lock);
+
+        // Like `systemd`, a service that was stopped on purpose is not restarted
+        if exit.stopped || !restart.applies(exit.termination, exit.watchdog) {
+            return Err(Error::ServiceExit(exit_code));
+        }
+
+        // An interval of 0 disables the start limit
+        starts.retain(|start| start.elapsed() < interval.into());
+        if interval != Seconds::ZERO && starts.len() >= usize::try_from(burst).unwrap_or(usize::MAX)
+        {
+            warn!("The service has hit its start limit, so it is not restarted");
+            status_sender
+                .send(Change {
+                    healthz: ChangeOperation::Keep,
+                    livez: ChangeOperation::Set(
+                        false,
+                        Reason {
+                            condition: Condition::Restart,
+                            message: format!(
+                                "The service has started {} times within {interval}",
+                                starts.len()
+                            ),
+                        },
+                    ),
+                    readyz: ChangeOperation::Keep,
+                })
+                .await
+                .map_err(Error::StatusChannelSend)?;
+            // The adapter keeps running, so that the failing probe restarts the container
+            tokio::select! {
+                () = token.cancelled() => return Ok(()),
+                _ = signal_receiver.recv() => return Err(Error::ServiceExit(exit_code)),
+            }
+        }
+
+        info!(status, "Restarting service in {}", restart_sec);
+        tokio::select! {
+            () = token.cancelled() => return Ok(()),
+            _ = signal_receiver.recv() => return Err(Error::ServiceExit(exit_code)),
+            () = time::sleep(restart_sec.into()) => {},
+        }
+        restarts += 1;
+        service.write().await.restarts = restarts;
+        status_sender
+            .send(Change {
+                healthz: ChangeOperation::Keep,
+                livez: ChangeOperation::Set(
+                    true,
+                    Reason {
+                        condition: Condition::Restart,
+                        message: format!("The service has restarted {restarts} times"),
+                    },
+                ),
+                readyz: ChangeOperation::Keep,
+            })
+            .await
+            .map_err(Error::StatusChannelSend)?;
+    }
+}
+
+/// When to restart the supervised service, like `Restart=`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    No,
+    OnFailure,
+    OnWatchdog,
+    Always,
+}
+
+impl Restart {
+    fn name(self) -> &'static str {
+        match self {
+            Self::No => "no",
+            Self::OnFailure => "on-failure",
+            Self::OnWatchdog => "on-watchdog",
+            Self::Always => "always",
+        }
+    }
+
+    /// Whether the service is restarted after it has terminated, possibly killed by the watchdog
+    fn applies(self, termination: Termination, watchdog: bool) -> bool {
+        match self {
+            Self::No => false,
+            Self::OnFailure => watchdog || !termination.clean(),
+            Self::OnWatchdog => watchdog,
+            Self::Always => true,
+        }
+    }
+}
+
+impl fmt::Display for Restart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Valuable for Restart {
+    fn as_value(&self) -> valuable::Value<'_> {
+        valuable::Value::String(self.name())
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+impl FromStr for Restart {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no" => Ok(Self::No),
+            "on-failure" => Ok(Self::OnFailure),
+            "on-watchdog" => Ok(Self::OnWatchdog),
+            "always" => Ok(Self::Always),
+            _ => Err(Error::ParseRestart(s.into())),
+        }
+    }
+}
+
+/// How the service has terminated
+#[derive(Clone, Copy)]
+enum Termination {
+    Exited(i32),
+    Signaled(Signal),
+}
+
+impl Termination {
+    /// The exit status, which is 128 plus the signal number if a signal terminated it, like in a
+    /// shell
+    fn status(self) -> i32 {
+        match self {
+            Self::Exited(code) => code,
+            Self::Signaled(signal) => 128 + signal as i32,
+        }
+    }
+
+    /// The exit code of the adapter, which is the status of an `EXIT_STATUS=` message unless a
+    /// signal has terminated the service. A reported status the adapter cannot exit with, since
+    /// only its lowest 8 bits would remain, is a failure.
+    fn exit_code(self, reported: Option<i32>) -> i32 {
+        match (self, reported) {
+            (Self::Exited(_), Some(reported)) if (0..=255).contains(&reported) => reported,
+            (Self::Exited(_), Some(_)) => EXIT_FAILURE,
+            _ => self.status(),
+        }
+    }
+
+    /// Whether `systemd` considers the termination clean rather than a failure
+    fn clean(self) -> bool {
+        matches!(
+            self,
+            Self::Exited(0)
+                | Self::Signaled(
+                    Signal::SIGHUP | Signal::SIGINT | Signal::SIGTERM | Signal::SIGPIPE
+                )
+        )
+    }
+}
+
+/// Why the service has stopped running
+struct Exit {
+    termination: Termination,
+    /// The adapter is shutting down
+    cancelled: bool,
+    /// A signal to stop the service has been forwarded to it
+    stopped: bool,
+    /// The watchdog has fired while the service was running
+    watchdog: bool,
+}
+
+/// Waits for the service to exit, stopping it when the adapter shuts down or a signal is
+/// forwarded to it
+async fn wait(
+    pid: Pid,
+    token: &CancellationToken,
+    config: &SharedConfiguration,
+    child: &mut unix::Signal,
+    signal_receiver: &mut Receiver<Signal>,
+    updates: &mut broadcast::Receiver<Update>,
+) -> Result<Exit, Error> {
+    let mut cancelled = false;
+    let mut stopped = false;
+    let mut watchdog = false;
+    let mut deadline = None;
+    // A closed channel would complete `recv` on every iteration
+    let mut signals = true;
+    loop {
+        let timeout = async {
+            match deadline {
+                Some(deadline) => time::sleep_until(deadline).await,
+                None => future::pending().await,
+            }
+        };
+        tokio::select! {
+            () = token.cancelled(), if !cancelled => {
+                cancelled = true;
+                deadline = stop(pid, Signal::SIGTERM, config).await?;
+            },
+            signal = signal_receiver.recv(), if signals => {
+                let Some(signal) = signal else {
+                    signals = false;
+                    continue;
+                };
+                info!(signal = signal.as_str(), "Forwarding signal to service");
+                stopped = true;
+                if deadline.is_none() {
+                    deadline = stop(pid, signal, config).await?;
+                } else {
+                    kill(pid, signal)?;
+                }
+            },
+            update = updates.recv() => {
+                // The watchdog timer signals the service, and missed updates are no reason to stop
+                if let Ok(Update::Event(Event::WatchdogTimeout | Event::WatchdogTrigger)) = update {
+                    watchdog = true;
+                }
+            },
+            () = timeout => {
+                warn!("The service did not stop within ADAPTER_UNIT_TIMEOUT_STOP_SEC");
+                kill(pid, Signal::SIGKILL)?;
+                deadline = None;
+            },
+            _ = child.recv() => {
+                if let Some(termination) = reap(pid)? {
+                    return Ok(Exit {
+                        termination,
+                        cancelled,
+                        stopped,
+                        watchdog,
+                    });
+                }
+            },
+        }
+    }
+}
+
+/// Sends a signal to stop the service, and returns when to kill it if it is still running
+async fn stop(
+    pid: Pid,
+    signal: Signal,
+    config: &SharedConfiguration,
+) -> Result<Option<Instant>, Error> {
+    kill(pid, signal)?;
+    let timeout = config.read().await.unit_timeout_stop_sec;
+    Ok((timeout != Seconds::INFINITY).then(|| Instant::now() + timeout.into()))
+}
+
+fn kill(pid: Pid, signal: Signal) -> Result<(), Error> {
+    match signal::kill(pid, signal) {
+        // The service has exited, but has not been reaped yet
+        Ok(()) | Err(Errno::ESRCH) => Ok(()),
+        Err(error) => Err(Error::SupervisorKill(error)),
+    }
+}
+
+/// Reaps every child that has exited, like an init process reaps orphans, and returns how the
+/// service has terminated if it is one of them
+fn reap(pid: Pid) -> Result<Option<Termination>, Error> {
+    let mut termination = None;
+    loop {
+        match wait::waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(child, code)) if child == pid => {
+                termination = Some(Termination::Exited(code));
+            }
+            Ok(WaitStatus::Signaled(child, signal, _)) if child == pid => {
+                termination = Some(Termination::Signaled(signal));
+            }
+            Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return Ok(termination),
+            Ok(_) | Err(Errno::EINTR) => {}
+            Err(error) => return Err(Error::SupervisorWait(error)),
+        }
+    }
+}
+
+/// Starts the service with the variables `systemd` passes to services of `Type=notify`
+///
+/// The standard library rather than `tokio` starts the service, since the process driver of
+/// `tokio` would reap it, racing with [`reap`]. Either way, the service starts with the default
+/// signal mask and a default `SIGPIPE` handler, like any process started by `systemd`.
+fn spawn(command: &[String], config: &Configuration) -> Result<Pid, Error> {
+    let program = command
+        .first()
+        .and_then(|program| resolve(program))
+        .ok_or_else(|| Error::SupervisorCommand(command.join(" ")))?;
+
+    let notify_socket = CString::new(&*config.notify_socket.0)
+        .map_err(|error| Error::SupervisorSpawn(error.into()))?;
+    let watchdog = ![Seconds::ZERO, Seconds::INFINITY].contains(&config.unit_watchdog_sec);
+    let watchdog_usec = watchdog
+        .then(|| CString::new(config.unit_watchdog_sec.as_microseconds().to_string()))
+        .transpose()
+        .map_err(|error| Error::SupervisorSpawn(error.into()))?;
+
+    let mut process = process::Command::new(program);
+    process.args(&command[1..]);
+    // `WATCHDOG_PID` is the PID of the child itself, so the child changes its own environment,
+    // which variables set with `env` would replace after this closure
+    // SAFETY: Between fork and exec, the closure only formats the PID on the stack and calls
+    // `setenv` and `unsetenv`, whose allocation glibc and musl support in the child of a fork
+    unsafe {
+        process.pre_exec(move || {
+            for variable in VARIABLES {
+                libc::unsetenv(variable.as_ptr());
+            }
+            set_variable(c"NOTIFY_SOCKET", &notify_socket)?;
+            if let Some(watchdog_usec) = &watchdog_usec {
+                set_variable(c"WATCHDOG_USEC", watchdog_usec)?;
+                let pid = decimal(unistd::getpid().as_raw());
+                set_variable(
+                    c"WATCHDOG_PID",
+                    CStr::from_bytes_until_nul(&pid).unwrap_or_default(),
+                )?;
+            }
+            Ok(())
+        });
+    }
+    // The child is reaped by `reap` rather than through the handle, which is not waited on
+    let child = process.spawn().map_err(Error::SupervisorSpawn)?;
+    // A PID is at most `PID_MAX_LIMIT`, which is far below `i32::MAX`
+    #[allow(clippy::cast_possible_wrap)]
+    Ok(Pid::from_raw(child.id() as i32))
+}
+
+/// Sets a variable in the environment of the child, before it executes the service
+fn set_variable(name: &CStr, value: &CStr) -> io::Result<()> {
+    // SAFETY: Both strings are terminated by a NUL byte, and only the child runs this
+    match unsafe { libc::setenv(name.as_ptr(), value.as_ptr(), 1) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// The decimal digits of a PID followed by a NUL byte, without allocating
+fn decimal(pid: i32) -> [u8; 12] {
+    let mut digits = [0u8; 12];
+    let mut remaining = pid.unsigned_abs();
+    let mut length = 0;
+    loop {
+        // A digit always fits in a byte
+        #[allow(clippy::cast_possible_truncation)]
+        let digit = (remaining % 10) as u8;
+        digits[length] = b'0' + digit;
+        remaining /= 10;
+        length += 1;
+        if remaining == 0 {
+            break;
+        }
+    }
+    digits[..length].reverse();
+    digits
+}
+
+/// Finds the path of a program like a shell does, in `PATH` if it has no slash
+fn resolve(program: &str) -> Option<PathBuf> {
+    let executable = |path: &PathBuf| {
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(executable);
+    }
+    let path =
+        env::var_os("PATH").unwrap_or_else(|| OsString::from("/usr/local/bin:/usr/bin:/bin"));
+    env::split_paths(&path)
+        .map(|directory| directory.join(program))
+        .find(executable)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn spawn_sets_notify_variables() {
+        let mut config = Configuration::defaults().expect("default configuration");
+        config.unit_watchdog_sec = Seconds(Duration::from_secs(30));
+        let script = format!(
+            "test \"$NOTIFY_SOCKET\" = '{}' && test \"$WATCHDOG_USEC\" = 30000000 && test \
+             \"$WATCHDOG_PID\" = $$",
+            config.notify_socket.0
+        );
+        let command = ["sh".into(), "-c".into(), script];
+        let pid = spawn(&command, &config).expect("service");
+        let status = wait::waitpid(pid, None).expect("exit status");
+        assert!(status == WaitStatus::Exited(pid, 0));
+    }
+
+    #[test]
+    fn spawn_sets_no_watchdog_variables_without_a_watchdog() {
+        let config = Configuration::defaults().expect("default configuration");
+        let script = "test -z \"$WATCHDOG_USEC\" && test -z \"$WATCHDOG_PID\"";
+        let command = ["sh".into(), "-c".into(), script.into()];
+        let pid = spawn(&command, &config).expect("service");
+        let status = wait::waitpid(pid, None).expect("exit status");
+        assert!(status == WaitStatus::Exited(pid, 0));
+    }
+
+    #[test]
+    fn decimal_pids_end_with_nul() {
+        assert_eq!(&decimal(0)[..2], b"0\0");
+        assert_eq!(&decimal(42)[..3], b"42\0");
+        assert_eq!(&decimal(i32::MAX)[..11], b"2147483647\0");
+    }
+
+    #[test]
+    fn exit_code_prefers_a_valid_exit_status() {
+        assert_eq!(Termination::Exited(0).exit_code(None), 0);
+        assert_eq!(Termination::Exited(0).exit_code(Some(3)), 3);
+        assert_eq!(Termination::Exited(0).exit_code(Some(256)), EXIT_FAILURE);
+        assert_eq!(Termination::Exited(0).exit_code(Some(-1)), EXIT_FAILURE);
+        assert_eq!(
+            Termination::Signaled(Signal::SIGKILL).exit_code(Some(3)),
+            137
+        );
+    }
+
+    #[test]
+    fn spawn_requires_an_executable() {
+        let config = Configuration::defaults().expect("default configuration");
+        let command = ["/nonexistent/service".into()];
+        assert!(matches!(
+            spawn(&command, &config),
+            Err(Error::SupervisorCommand(_))
+        ));
+    }
+}
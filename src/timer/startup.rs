@@ -1,5 +1,6 @@
-use std::{f64::INFINITY, time::Duration};
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use tokio::{sync::mpsc::Sender, time::sleep};
 use tokio_util::sync::CancellationToken;
 use tracing::{info, instrument};
@@ -8,6 +9,7 @@ use crate::{
     config::{Seconds, SharedConfiguration},
     error::Error,
     event::Event,
+    service::SharedService,
     status::SharedStatus,
 };
 
@@ -16,9 +18,11 @@ pub async fn timer(
     token: CancellationToken,
     config: SharedConfiguration,
     status: SharedStatus,
+    service: SharedService,
     ready_sender: Sender<()>,
     event_sender: Sender<Event>,
 ) -> Result<(), Error> {
+    let started = Utc::now();
     let mut timeout = config.read().await.unit_timeout_start_sec;
 
     info!("Startup timer ready");
@@ -27,10 +31,12 @@ pub async fn timer(
         .await
         .map_err(Error::ReadyChannelSend)?;
 
-    if timeout == Seconds(INFINITY) {
+    if timeout == Seconds::INFINITY {
         return Ok(());
     }
 
+    service.write().await.startup_deadline = deadline(started, timeout);
+
     let mut sleep_duration = timeout.into();
     loop {
         tokio::select! {
@@ -42,11 +48,12 @@ pub async fn timer(
         if timeout == new_timeout {
             break;
         }
-        sleep_duration = Duration::from(new_timeout) - timeout.into();
+        sleep_duration = Duration::from(new_timeout).saturating_sub(timeout.into());
         timeout = new_timeout;
+        service.write().await.startup_deadline = deadline(started, timeout);
     }
 
-    let ready = status.read().await.readyz;
+    let ready = status.read().await.readyz.value();
     if !ready {
         event_sender
             .send(Event::StartTimeout)
@@ -58,3 +65,8 @@ pub async fn timer(
 
     Ok(())
 }
+
+fn deadline(started: DateTime<Utc>, timeout: Seconds) -> Option<DateTime<Utc>> {
+    let timeout = chrono::Duration::from_std(timeout.into()).ok()?;
+    started.checked_add_signed(timeout)
+}
@@ -1,29 +1,44 @@
 use std::{
-    future::{self, Future},
-    pin::Pin,
-    task::{Context, Poll},
+    future,
     time::{Duration, Instant},
 };
 
+use chrono::{DateTime, Utc};
+use nix::{
+    errno::Errno,
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
 use tokio::{
     sync::mpsc::{Receiver, Sender},
-    time::sleep,
+    time::{self, sleep},
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
-use crate::{config::SharedConfiguration, error::Error, event::Event};
+use crate::{
+    config::{Seconds, SharedConfiguration},
+    error::Error,
+    event::Event,
+    service::SharedService,
+};
 
 #[instrument(name = "Watchdog timer", skip_all)]
 pub async fn timer(
     token: CancellationToken,
     config: SharedConfiguration,
+    service: SharedService,
     mut watchdog_receiver: Receiver<Message>,
     ready_sender: Sender<()>,
     event_sender: Sender<Event>,
 ) -> Result<(), Error> {
     let mut duration: Duration = config.read().await.unit_watchdog_sec.into();
     let mut last_timestamp = Instant::now();
+    let mut last_ping = Utc::now();
+    service.write().await.watchdog_deadline = deadline(last_ping, duration);
+    // Whether the watchdog signal was sent since the last ping, and when to kill the main process
+    let mut signaled = false;
+    let mut abort = None;
 
     info!("Watchdog timer ready");
     ready_sender
@@ -39,10 +54,17 @@ pub async fn timer(
                 sleep(duration).await;
             }
         };
+        let escalation = async {
+            match abort {
+                Some((_, deadline)) => time::sleep_until(deadline).await,
+                None => future::pending::<()>().await,
+            }
+        };
         let message = tokio::select! {
             () = token.cancelled() => break,
             message = watchdog_receiver.recv() => message.ok_or(Error::WatchdogChannelClosed)?,
             () = timeout => Message::Wake,
+            () = escalation => Message::Escalate,
         };
         match message {
             Message::Wake => {
@@ -53,13 +75,52 @@ pub async fn timer(
                         .send(Event::WatchdogTimeout)
                         .await
                         .map_err(Error::EventChannelSend)?;
+                    // The timeout is reported again on every wake, but the signal is sent once
+                    if !signaled {
+                        signaled = true;
+                        abort = send_signal(&config, &service).await;
+                    }
                 }
             }
             Message::KeepAlive | Message::Trigger => {
                 last_timestamp = Instant::now();
+                last_ping = Utc::now();
+                let mut service_lock = service.write().await;
+                service_lock.watchdog_ping = Some(last_ping);
+                service_lock.watchdog_deadline = deadline(last_ping, duration);
+                drop(service_lock);
+                // Like `systemd`, a trigger is handled like a timeout
+                if matches!(message, Message::Trigger) {
+                    if !signaled {
+                        signaled = true;
+                        abort = send_signal(&config, &service).await;
+                    }
+                } else {
+                    signaled = false;
+                }
             } // Status change handled in event listener
+            // Like `systemd`, a service that has started gets a full timeout for its first ping
+            Message::Start => {
+                last_timestamp = Instant::now();
+                signaled = false;
+                abort = None;
+                service.write().await.watchdog_deadline = deadline(Utc::now(), duration);
+            }
+            Message::Escalate => {
+                if let Some((pid, _)) = abort.take() {
+                    // The main process may have exited and been replaced in the meantime
+                    if service.read().await.main_pid == Some(pid.as_raw()) {
+                        warn!(
+                            pid = pid.as_raw(),
+                            "The main process did not exit within ADAPTER_UNIT_TIMEOUT_ABORT_SEC"
+                        );
+                        kill(pid, Signal::SIGKILL);
+                    }
+                }
+            }
             Message::NewTimeout => {
                 duration = config.read().await.unit_watchdog_sec.into();
+                service.write().await.watchdog_deadline = deadline(last_ping, duration);
             }
         }
     }
@@ -73,16 +134,54 @@ pub enum Message {
     Wake,
     KeepAlive,
     Trigger,
+    Start,
     NewTimeout,
+    Escalate,
 }
 
-struct NeverYield;
+/// Sends `ADAPTER_UNIT_WATCHDOG_SIGNAL` to the main process if it is known, and returns it along
+/// with when to kill it if it is still running
+async fn send_signal(
+    config: &SharedConfiguration,
+    service: &SharedService,
+) -> Option<(Pid, time::Instant)> {
+    let pid = service.read().await.main_process()?;
+
+    let config_lock = config.read().await;
+    let signal = config_lock.unit_watchdog_signal.0;
+    let timeout = config_lock
+        .unit_timeout_abort_sec
+        .unwrap_or(config_lock.unit_timeout_stop_sec);
+    drop(config_lock);
+
+    warn!(
+        pid = pid.as_raw(),
+        signal = signal.as_str(),
+        "Sending the watchdog signal to the main process"
+    );
+    if !kill(pid, signal) {
+        return None;
+    }
+    (signal != Signal::SIGKILL && timeout != Seconds::INFINITY)
+        .then(|| (pid, time::Instant::now() + timeout.into()))
+}
 
-impl Future for NeverYield {
-    type Output = ();
+/// Sends a signal to a process and returns whether it was delivered, since not being able to
+/// signal the service is no reason to shut down the adapter
+fn kill(pid: Pid, signal: Signal) -> bool {
+    match signal::kill(pid, signal) {
+        Ok(()) => true,
+        Err(Errno::ESRCH) => false,
+        Err(error) => {
+            warn!("Could not send {} to the main process: {}", signal, error);
+            false
+        }
+    }
+}
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
-        // Always return Pending, never ready to make progress
-        Poll::Pending
+fn deadline(ping: DateTime<Utc>, duration: Duration) -> Option<DateTime<Utc>> {
+    if duration.is_zero() {
+        return None;
     }
+    ping.checked_add_signed(chrono::Duration::from_std(duration).ok()?)
 }
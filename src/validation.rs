@@ -0,0 +1,264 @@
+use std::fmt;
+
+use crate::{
+    config::{self, Configuration, Seconds},
+    event::{Event, EventList},
+    server::admin::Listen,
+    supervisor::Restart,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A logical mistake in a configuration whose values could all be parsed
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// Finds the problems of a configuration, where errors prevent the adapter from using it
+pub fn validate(config: &Configuration) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    problems.extend(event_problems(config));
+    problems.extend(admin_problems(config));
+    problems.extend(value_problems(config));
+    problems.extend(job_problems(config));
+    problems.extend(kubernetes_problems(config));
+    problems
+}
+
+pub fn warning(message: String) -> Problem {
+    Problem {
+        severity: Severity::Warning,
+        message,
+    }
+}
+
+fn error(message: String) -> Problem {
+    Problem {
+        severity: Severity::Error,
+        message,
+    }
+}
+
+fn event_problems(config: &Configuration) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for (probe, true_key, true_list, false_key, false_list) in [
+        (
+            "/livez",
+            "status_livez_true",
+            &config.status_livez_true,
+            "status_livez_false",
+            &config.status_livez_false,
+        ),
+        (
+            "/readyz",
+            "status_readyz_true",
+            &config.status_readyz_true,
+            "status_readyz_false",
+            &config.status_readyz_false,
+        ),
+    ] {
+        for event in true_list.iter().filter(|event| false_list.contains(*event)) {
+            problems.push(warning(format!(
+                "{event} is in both {} and {}, so it changes {probe} to false",
+                config::variable(true_key),
+                config::variable(false_key),
+            )));
+        }
+    }
+
+    // The default lists name every event, so only lists that were changed are reported
+    if let Ok(defaults) = Configuration::defaults() {
+        for (key, list, default) in [
+            (
+                "status_livez_true",
+                &config.status_livez_true,
+                &defaults.status_livez_true,
+            ),
+            (
+                "status_livez_false",
+                &config.status_livez_false,
+                &defaults.status_livez_false,
+            ),
+            (
+                "status_readyz_true",
+                &config.status_readyz_true,
+                &defaults.status_readyz_true,
+            ),
+            (
+                "status_readyz_false",
+                &config.status_readyz_false,
+                &defaults.status_readyz_false,
+            ),
+            (
+                "status_shutdown",
+                &config.status_shutdown,
+                &defaults.status_shutdown,
+            ),
+        ] {
+            if list != default {
+                for (event, reason) in unreachable_events(config, list) {
+                    problems.push(warning(format!(
+                        "{event} is in {}, but {reason}",
+                        config::variable(key)
+                    )));
+                }
+            }
+        }
+    }
+
+    if config.unit_restart != Restart::No && config.status_shutdown.contains(Event::Exit(0)) {
+        problems.push(warning(format!(
+            "ADAPTER_STATUS_SHUTDOWN contains exit, so the adapter shuts down instead of restarting the service like ADAPTER_UNIT_RESTART is {}",
+            config.unit_restart
+        )));
+    }
+
+    for event in config.status_shutdown_codes.events() {
+        if !config.status_shutdown.contains(event) {
+            problems.push(warning(format!(
+                "{event} is in ADAPTER_STATUS_SHUTDOWN_CODES, but not in ADAPTER_STATUS_SHUTDOWN"
+            )));
+        }
+    }
+
+    if config.status_shutdown.contains(Event::Ready) {
+        problems.push(error(String::from(
+            "ADAPTER_STATUS_SHUTDOWN contains ready, so the adapter would shut down as soon as the service is ready",
+        )));
+    }
+
+    problems
+}
+
+fn job_problems(config: &Configuration) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if !config.job {
+        if config.job_silence_sec != Seconds::INFINITY {
+            problems.push(warning(String::from(
+                "ADAPTER_JOB_SILENCE_SEC is set, but ADAPTER_JOB is false",
+            )));
+        }
+    } else if config.status_shutdown.contains(Event::Stopping) {
+        problems.push(warning(String::from(
+            "ADAPTER_STATUS_SHUTDOWN contains stopping, so the adapter shuts down before the job has sent EXIT_STATUS=",
+        )));
+    }
+
+    problems
+}
+
+fn kubernetes_problems(config: &Configuration) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if config.kubernetes_condition.is_some() && config.kubernetes_pod_name.is_none() {
+        problems.push(error(String::from(
+            "ADAPTER_KUBERNETES_CONDITION is set, but ADAPTER_KUBERNETES_POD_NAME is not",
+        )));
+    }
+    if !config.kubernetes_events.is_empty() && config.kubernetes_pod_name.is_none() {
+        problems.push(error(String::from(
+            "ADAPTER_KUBERNETES_EVENTS is set, but ADAPTER_KUBERNETES_POD_NAME is not",
+        )));
+    }
+
+    problems
+}
+
+fn admin_problems(config: &Configuration) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    match &config.admin_address.listen {
+        None => {
+            if config.admin_token.is_some() {
+                problems.push(warning(String::from(
+                    "ADAPTER_ADMIN_TOKEN is set, but the admin server is disabled",
+                )));
+            }
+            if config.admin_notify {
+                problems.push(warning(String::from(
+                    "ADAPTER_ADMIN_NOTIFY is true, but the admin server is disabled",
+                )));
+            }
+        }
+        Some(Listen::Tcp(address))
+            if !address.ip().is_loopback() && config.admin_token.is_none() =>
+        {
+            problems.push(warning(format!(
+                "The admin server listens on {address} without ADAPTER_ADMIN_TOKEN"
+            )));
+        }
+        Some(_) => {}
+    }
+
+    // Port 0 picks any free port
+    let mut ports = vec![("port", config.port)];
+    if let Some(port) = config.grpc_port {
+        ports.push(("grpc_port", port));
+    }
+    if let Some(Listen::Tcp(address)) = &config.admin_address.listen {
+        ports.push(("admin_address", address.port()));
+    }
+    for (index, (key, port)) in ports.iter().enumerate() {
+        for (other_key, _) in ports[..index]
+            .iter()
+            .filter(|(_, other_port)| *port != 0 && other_port == port)
+        {
+            problems.push(error(format!(
+                "{} and {} both use port {port}",
+                config::variable(other_key),
+                config::variable(key)
+            )));
+        }
+    }
+
+    problems
+}
+
+fn value_problems(config: &Configuration) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if config.channel_size == 0 {
+        problems.push(error(String::from(
+            "ADAPTER_CHANNEL_SIZE must be at least 1",
+        )));
+    }
+
+    problems
+}
+
+/// The events of a list that can never occur with the configuration, and why
+fn unreachable_events(config: &Configuration, list: &EventList) -> Vec<(Event, &'static str)> {
+    let watchdog = ![Seconds::ZERO, Seconds::INFINITY].contains(&config.unit_watchdog_sec);
+    list.iter()
+        .filter_map(|event| match event {
+            Event::WatchdogTimeout if !watchdog && !config.allow_message_watchdog_usec => Some((
+                event,
+                "ADAPTER_UNIT_WATCHDOG_SEC disables the watchdog and ADAPTER_ALLOW_MESSAGE_WATCHDOG_USEC is false",
+            )),
+            Event::WatchdogTimeout if !watchdog => Some((
+                event,
+                "ADAPTER_UNIT_WATCHDOG_SEC disables the watchdog, so it only occurs after a WATCHDOG_USEC= message",
+            )),
+            Event::StartTimeout if config.unit_timeout_start_sec == Seconds::INFINITY => {
+                Some((event, "ADAPTER_UNIT_TIMEOUT_START_SEC is infinity"))
+            }
+            _ => None,
+        })
+        .collect()
+}